@@ -0,0 +1,132 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use cache::MenuCache;
+use date::Date;
+use levenshtein;
+use menu::Menu;
+use storage::{Alerts, Storage};
+
+const INTERVAL: u64 = 600;
+
+fn split_words(s: &str) -> Vec<String> {
+	s.to_lowercase()
+			.split(|c: char| !c.is_alphanumeric())
+			.filter(|x| !x.is_empty())
+			.map(String::from)
+			.collect()
+}
+
+/// Whether every word of `keyword` occurs in `name`, allowing one typo per five
+/// characters and compound words like "Kartoffelpuffer" for "puffer".
+pub fn matches(keyword: &str, name: &str) -> bool {
+	let words = split_words(name);
+	let kws   = split_words(keyword);
+	!kws.is_empty() && kws.iter().all(|kw| {
+		let n = kw.chars().count();
+		words.iter().any(|w| {
+			if w.contains(kw.as_str()) {
+				return true;
+			}
+			let typo = levenshtein::distance(kw, w) * 5 <= n;
+			if typo {
+				debug!("alert keyword {} matches {}: {:?}", kw, w, levenshtein::edit_script(kw, w));
+			}
+			typo
+		})
+	})
+}
+
+/// Apply `f` on a user's alerts, creating them if necessary, and remove them
+/// if no keywords are left afterwards.
+pub fn modify<F, R>(storage: &mut Storage, user: i64, f: F) -> R where F: FnOnce(&mut Alerts) -> R {
+	let r = f(storage.state.alerts.entry(user).or_insert_with(Alerts::default));
+	if storage.state.alerts.get(&user).map_or(false, |x| x.keywords.is_empty()) {
+		storage.state.alerts.remove(&user);
+	}
+	r
+}
+
+fn collect_matches(alerts: &mut Alerts, today: &str, days: &Vec<(&str, Menu)>) -> Vec<String> {
+	if alerts.announced_on.as_ref().map_or(true, |x| x != today) {
+		alerts.announced.clear();
+		alerts.announced_on = Some(String::from(today));
+	}
+
+	let mut lines = Vec::new();
+	for &(label, ref menu) in days.iter() {
+		for (mensa, meals) in menu.iter() {
+			for meal in meals.iter() {
+				if alerts.announced.contains(&meal.name) || !alerts.keywords.iter().any(|kw| matches(kw, &meal.name)) {
+					continue;
+				}
+				lines.push(format!("{}, {}:\n * {}", label, mensa, meal));
+				alerts.announced.insert(meal.name.clone());
+			}
+		}
+	}
+	lines
+}
+
+/// Look for alerted meals on today's and tomorrow's menus every few minutes,
+/// `send` is called with the user ID and the message text and returns whether
+/// the meals are done, i.e. delivered or undeliverable.
+pub fn spawn<F>(storage: Arc<Mutex<Storage>>, cache: Arc<MenuCache>, send: F) -> thread::JoinHandle<()>
+		where F: Fn(i64, String) -> bool + Send + 'static {
+	thread::spawn(move || {
+		loop {
+			thread::sleep(Duration::from_secs(INTERVAL));
+
+			if storage.lock().unwrap().state.alerts.is_empty() {
+				continue;
+			}
+
+			let today = Date::today();
+			let mut days = Vec::new();
+			for &(label, day) in [("Heute", today), ("Morgen", today.add_days(1))].iter() {
+				match cache.get(day) {
+					Err(e) => warn!("cannot fetch menu for alerts: {}", e),
+					Ok(c)  => days.push((label, c.menu))
+				}
+			}
+			let today = today.to_string();
+
+			// do not block the storage while sending
+			let mut pending = Vec::new();
+			for (user, alerts) in storage.lock().unwrap().state.alerts.iter() {
+				let mut alerts = alerts.clone();
+				let lines = collect_matches(&mut alerts, &today, &days);
+				if !lines.is_empty() {
+					pending.push((*user, alerts, lines));
+				}
+			}
+
+			let mut sent = Vec::new();
+			for (user, alerts, lines) in pending {
+				info!("sending {} alerts to {}", lines.len(), user);
+				if send(user, lines.join("\n\n")) {
+					sent.push((user, alerts));
+				}
+			}
+
+			if !sent.is_empty() {
+				let mut storage = storage.lock().unwrap();
+				for (user, alerts) in sent {
+					// keywords might have changed in the meantime
+					match storage.state.alerts.get_mut(&user) {
+						None    => {},
+						Some(x) => {
+							x.announced_on = alerts.announced_on;
+							x.announced    = alerts.announced;
+						}
+					}
+				}
+				match storage.save() {
+					Err(e) => error!("cannot save state: {}", e),
+					Ok(_)  => {}
+				}
+			}
+		}
+	})
+}
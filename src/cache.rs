@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+extern crate time;
+
+use date::Date;
+use menu::Menu;
+use source::MenuSource;
+
+struct Entry {
+	menu:    Menu,
+	fetched: time::Tm,
+	age:     Instant
+}
+
+pub struct Cached {
+	pub menu:    Menu,
+	pub fetched: time::Tm,
+	/// The menu could not be refreshed and is older than the TTL.
+	pub stale:   bool
+}
+impl Cached {
+	pub fn timestamp(&self) -> String {
+		match self.fetched.strftime("%d.%m.%Y %H:%M") {
+			Err(_) => String::from("?"),
+			Ok(x)  => x.to_string()
+		}
+	}
+}
+
+/// Keeps the parsed day menus of a `MenuSource` for a while, when the source
+/// fails the last known copy is used.
+pub struct MenuCache {
+	source:  Box<dyn MenuSource>,
+	ttl:     Duration,
+	entries: Mutex<HashMap<Date, Entry>>
+}
+impl MenuCache {
+	pub fn new(source: Box<dyn MenuSource>, ttl: u64) -> MenuCache {
+		MenuCache {
+			source:  source,
+			ttl:     Duration::from_secs(ttl),
+			entries: Mutex::new(HashMap::new())
+		}
+	}
+
+	pub fn source(&self) -> &dyn MenuSource {
+		&*self.source
+	}
+
+	fn lookup(&self, day: Date) -> Option<Cached> {
+		let entries = self.entries.lock().unwrap();
+		entries.get(&day).map(|e| Cached {
+			menu:    e.menu.clone(),
+			fetched: e.fetched,
+			stale:   e.age.elapsed() >= self.ttl
+		})
+	}
+
+	/// Fetch the menu of `day` from the source regardless of its age, the
+	/// lock is not held during the request.
+	pub fn refresh(&self, day: Date) -> Result<Cached, String> {
+		let menu = self.source.menu(day)?;
+		let now  = time::now();
+		let mut entries = self.entries.lock().unwrap();
+		// forget days that are gone
+		let yesterday = Date::today().add_days(-1);
+		entries.retain(|d, _| *d >= yesterday);
+		entries.insert(day, Entry {
+			menu:    menu.clone(),
+			fetched: now,
+			age:     Instant::now()
+		});
+		Ok(Cached {
+			menu:    menu,
+			fetched: now,
+			stale:   false
+		})
+	}
+
+	pub fn get(&self, day: Date) -> Result<Cached, String> {
+		let cached = self.lookup(day);
+		if cached.as_ref().map_or(false, |c| !c.stale) {
+			debug!("menu for {} from cache", day);
+			return Ok(cached.unwrap());
+		}
+		match self.refresh(day) {
+			Ok(x)  => Ok(x),
+			Err(e) => match cached {
+				None    => Err(e),
+				Some(c) => {
+					warn!("cannot refresh menu for {}, using copy of {}: {}", day, c.timestamp(), e);
+					Ok(c)
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,163 @@
+use tg;
+
+use Bot;
+
+/// A `/command[@botname] [argument]` in a message.
+pub struct Command {
+	/// lowercase, without the leading slash and bot name
+	pub name: String,
+	/// text up to the next entity
+	pub arg:  Option<String>
+}
+
+/// Extract all commands in `msg` addressed to `botname` or to no bot in
+/// particular.
+pub fn parse(msg: &tg::Message, botname: Option<&str>) -> Vec<Command> {
+	let text: Vec<u16> = match msg.text {
+		None        => return Vec::new(),
+		Some(ref t) => t.encode_utf16().collect()
+	};
+
+	let mut cmds = Vec::new();
+	for ent in msg.entities.iter().filter(|x| x.entity_type == "bot_command") {
+		let cmd = match ent.extract(&text) {
+			Err(e) => {
+				error!("cannot extract entity: {}", e);
+				continue;
+			},
+			Ok(x) => x
+		};
+		let mut parts = cmd.trim_left_matches('/').splitn(2, '@');
+		let name = parts.next().unwrap_or("").to_lowercase();
+		match (parts.next(), botname) {
+			(Some(to), Some(me)) if to.to_lowercase() != me.to_lowercase() => {
+				debug!("command /{} is addressed to {}", name, to);
+				continue;
+			},
+			_ => {}
+		}
+
+		let start = ent.offset + ent.length;
+		let end = msg.entities.iter()
+				.map(|x| x.offset)
+				.filter(|&x| x >= start)
+				.min()
+				.unwrap_or(text.len());
+		let argent = tg::MessageEntity {
+			entity_type: String::new(),
+			offset:      start,
+			length:      end.saturating_sub(start)
+		};
+		let arg = match argent.extract(&text) {
+			Err(e) => {
+				error!("cannot extract argument: {}", e);
+				None
+			},
+			Ok(x) => {
+				let x = x.trim();
+				if x.is_empty() { None } else { Some(String::from(x)) }
+			}
+		};
+
+		cmds.push(Command {
+			name: name,
+			arg:  arg
+		});
+	}
+	cmds
+}
+
+/// Description of a command for users.
+pub struct CommandInfo {
+	/// without the leading slash
+	pub name:           &'static str,
+	/// usage of the arguments, empty if the command takes none
+	pub args:           &'static str,
+	pub description:    &'static str,
+	pub description_en: &'static str,
+	/// only suggested in private chats
+	pub private:        bool
+}
+
+/// Creates the reply to a command given its lowercase argument.
+pub trait Handler {
+	fn handle(&self, bot: &Bot, msg: &tg::Message, arg: Option<&str>) -> tg::OutgoingText;
+}
+impl<F> Handler for F where F: Fn(&Bot, &tg::Message, Option<&str>) -> tg::OutgoingText {
+	fn handle(&self, bot: &Bot, msg: &tg::Message, arg: Option<&str>) -> tg::OutgoingText {
+		self(bot, msg, arg)
+	}
+}
+
+struct Entry {
+	info:    CommandInfo,
+	/// what the reply is, for logging
+	what:    &'static str,
+	handler: Box<dyn Handler>
+}
+
+/// Registry of the commands the bot understands.
+pub struct Router {
+	entries: Vec<Entry>
+}
+impl Router {
+	pub fn new() -> Router {
+		Router {
+			entries: Vec::new()
+		}
+	}
+
+	pub fn register<H: Handler + 'static>(&mut self, info: CommandInfo, what: &'static str, handler: H) {
+		self.entries.push(Entry {
+			info:    info,
+			what:    what,
+			handler: Box::new(handler)
+		});
+	}
+
+	pub fn commands<'a>(&'a self) -> Box<dyn Iterator<Item=&'a CommandInfo> + 'a> {
+		Box::new(self.entries.iter().map(|x| &x.info))
+	}
+
+	/// Replies to `cmds` in order, every command is only answered once.
+	pub fn dispatch(&self, bot: &Bot, msg: &tg::Message, cmds: &[Command]) -> Vec<(&'static str, tg::OutgoingText)> {
+		let mut seen = Vec::new();
+		let mut replies = Vec::new();
+		for cmd in cmds.iter() {
+			if seen.contains(&cmd.name) {
+				continue;
+			}
+			match self.entries.iter().find(|x| x.info.name == cmd.name) {
+				None    => debug!("unknown command /{}", cmd.name),
+				Some(x) => {
+					let arg = cmd.arg.as_ref().map(|x| x.to_lowercase());
+					replies.push((x.what, x.handler.handle(bot, msg, arg.as_ref().map(String::as_str))));
+					seen.push(cmd.name.clone());
+				}
+			}
+		}
+		replies
+	}
+
+	/// Command list for `setMyCommands`, in English if `english` is set.
+	fn bot_commands(&self, private: bool, english: bool) -> Vec<tg::BotCommand> {
+		self.commands()
+				.filter(|x| private || !x.private)
+				.map(|x| tg::BotCommand {
+					command:     String::from(x.name),
+					description: String::from(if english { x.description_en } else { x.description })
+				})
+				.collect()
+	}
+
+	/// Command lists for every scope and language, as scope, language code and
+	/// commands.
+	pub fn command_lists(&self) -> Vec<(&'static str, Option<&'static str>, Vec<tg::BotCommand>)> {
+		let mut lists = Vec::new();
+		for &(scope, private) in [("all_private_chats", true), ("all_group_chats", false)].iter() {
+			lists.push((scope, None, self.bot_commands(private, false)));
+			lists.push((scope, Some("en"), self.bot_commands(private, true)));
+		}
+		lists
+	}
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fs::File;
 use std::io::Read;
@@ -8,6 +8,7 @@ use std::time::Duration;
 
 extern crate toml;
 
+use levenshtein;
 use tg;
 
 #[derive(Deserialize)]
@@ -22,7 +23,13 @@ pub struct ConfigGeneral {
 	#[serde(default="ConfigGeneral::default_mensas")]
 	pub mensas:    Vec<String>,
 	#[serde(default)]
-	pub patterns:  Vec<String>
+	pub patterns:  Vec<String>,
+	#[serde(default="ConfigGeneral::default_cachettl")]
+	pub cachettl:  u64,
+	#[serde(default)]
+	pub notify:    bool,
+	#[serde(default="ConfigGeneral::default_threshold")]
+	pub threshold: f64
 }
 impl ConfigGeneral {
 	fn default_tomorrow() -> String {
@@ -37,6 +44,14 @@ impl ConfigGeneral {
 		30
 	}
 
+	fn default_cachettl() -> u64 {
+		600
+	}
+
+	fn default_threshold() -> f64 {
+		0.5
+	}
+
 	fn default_mensas() -> Vec<String> {
 		vec![
 			String::from("Alte Mensa"),
@@ -44,16 +59,40 @@ impl ConfigGeneral {
 		]
 	}
 
-	pub fn retry<E, F, R>(&self, msg: &str, mut action: F) -> Result<R, ()>
-			where E: fmt::Display, F: FnMut() -> Result<R, E> {
+	/// Repeat a Telegram Bot API request until it succeeds, fails permanently
+	/// or `retries` is exhausted, waiting as long as Telegram asks to when flood
+	/// control kicks in. Returns the last error on failure.
+	pub fn retry<F, R>(&self, msg: &str, mut action: F) -> Result<R, tg::Error>
+			where F: FnMut() -> Result<R, tg::Error> {
 		let mut fails = 0;
 		loop {
 			match action() {
 				Err(e) => {
+					match e.migrate_to_chat_id() {
+						None     => {},
+						Some(id) => {
+							error!("cannot {}: {}, chat was migrated to {}", msg, e, id);
+							return Err(e);
+						}
+					}
+					match e.retry_after() {
+						None       => {},
+						Some(secs) => {
+							// not a failure, the request will succeed afterwards
+							warn!("cannot {}: {}, retrying in {} seconds...", msg, e, secs);
+							thread::sleep(Duration::from_secs(secs));
+							continue;
+						}
+					}
+					if !e.is_retriable() {
+						error!("cannot {}, giving up: {}", msg, e);
+						return Err(e);
+					}
+
 					fails += 1;
 					if fails >= self.retries && self.retries > 0 {
 						error!("cannot {} (try {}/{}): {}", msg, fails, self.retries, e);
-						return Err(());
+						return Err(e);
 					} else if self.retrywait == 0 {
 						warn!("cannot {}: {}, retrying...", msg, e);
 						fails -= 1; // do not count fails
@@ -68,6 +107,108 @@ impl ConfigGeneral {
 	}
 }
 
+#[derive(Deserialize)]
+pub struct ConfigSource {
+	#[serde(rename="type", default="ConfigSource::default_type")]
+	pub source_type: String,
+	pub url:         Option<String>,
+	pub city:        Option<String>,
+	#[serde(default)]
+	pub canteens:    Vec<u64>
+}
+impl ConfigSource {
+	fn new() -> ConfigSource {
+		ConfigSource {
+			source_type: ConfigSource::default_type(),
+			url:         None,
+			city:        None,
+			canteens:    Vec::new()
+		}
+	}
+
+	fn default_type() -> String {
+		String::from("studentenwerk")
+	}
+}
+
+#[derive(Deserialize)]
+pub struct ConfigPrefetch {
+	#[serde(default="ConfigPrefetch::default_interval")]
+	pub interval: u64,
+	#[serde(default="ConfigPrefetch::default_from")]
+	pub from:     String,
+	#[serde(default="ConfigPrefetch::default_until")]
+	pub until:    String
+}
+impl ConfigPrefetch {
+	fn new() -> ConfigPrefetch {
+		ConfigPrefetch {
+			interval: ConfigPrefetch::default_interval(),
+			from:     ConfigPrefetch::default_from(),
+			until:    ConfigPrefetch::default_until()
+		}
+	}
+
+	fn default_interval() -> u64 {
+		900
+	}
+
+	fn default_from() -> String {
+		String::from("06:00:00")
+	}
+
+	fn default_until() -> String {
+		String::from("15:00:00")
+	}
+}
+
+#[derive(Deserialize)]
+pub struct ConfigStorage {
+	#[serde(rename="type", default="ConfigStorage::default_type")]
+	pub storage_type: String,
+	#[serde(default="ConfigStorage::default_path")]
+	pub path:         String
+}
+impl ConfigStorage {
+	fn new() -> ConfigStorage {
+		ConfigStorage {
+			storage_type: ConfigStorage::default_type(),
+			path:         ConfigStorage::default_path()
+		}
+	}
+
+	fn default_type() -> String {
+		String::from("json")
+	}
+
+	fn default_path() -> String {
+		String::from("mensabot.json")
+	}
+}
+
+#[derive(Deserialize)]
+pub struct ConfigWebhook {
+	#[serde(default)]
+	pub url:    String,
+	#[serde(default="ConfigWebhook::default_listen")]
+	pub listen: String,
+	#[serde(default)]
+	pub secret: String
+}
+impl ConfigWebhook {
+	fn new() -> ConfigWebhook {
+		ConfigWebhook {
+			url:    String::new(),
+			listen: ConfigWebhook::default_listen(),
+			secret: String::new()
+		}
+	}
+
+	fn default_listen() -> String {
+		String::from("127.0.0.1:8443")
+	}
+}
+
 #[derive(Deserialize)]
 pub struct ConfigAccess {
 	#[serde(rename="chats", default)]
@@ -116,13 +257,43 @@ impl ConfigAccess {
 	}
 }
 
+/// Aliases and groups by their normalized names.
+fn shortcuts(aliases: &BTreeMap<String, String>, groups: &BTreeMap<String, Vec<String>>) -> Result<BTreeMap<String, Vec<String>>, String> {
+	let mut shortcuts = BTreeMap::new();
+	let names = aliases.iter().map(|(x, mensa)| (x, vec![mensa.clone()]))
+			.chain(groups.iter().map(|(x, mensas)| (x, mensas.clone())));
+	for (short, mensas) in names {
+		if shortcuts.insert(levenshtein::normalize(short), mensas).is_some() {
+			return Err(format!("'{}' is used by several aliases or groups", short));
+		}
+	}
+	Ok(shortcuts)
+}
+
 #[derive(Deserialize)]
 pub struct Config {
 	pub general: ConfigGeneral,
+	#[serde(default="ConfigSource::new")]
+	pub source: ConfigSource,
+	#[serde(default="ConfigPrefetch::new")]
+	pub prefetch: ConfigPrefetch,
+	#[serde(default="ConfigStorage::new")]
+	pub storage: ConfigStorage,
+	#[serde(default="ConfigWebhook::new")]
+	pub webhook: ConfigWebhook,
 	#[serde(default="ConfigAccess::new")]
 	pub allow: ConfigAccess,
 	#[serde(default="ConfigAccess::new")]
-	pub deny: ConfigAccess
+	pub deny: ConfigAccess,
+	/// short names of single canteens
+	#[serde(default)]
+	pub aliases: BTreeMap<String, String>,
+	/// names of several canteens
+	#[serde(default)]
+	pub groups: BTreeMap<String, Vec<String>>,
+	/// aliases and groups by their normalized names
+	#[serde(skip)]
+	pub shortcuts: BTreeMap<String, Vec<String>>
 }
 impl Config {
 	pub fn load<P: AsRef<Path>>(name: P) -> Result<Config, String> where P: fmt::Display {
@@ -135,17 +306,43 @@ impl Config {
 		if conf.general.mensas.is_empty() {
 			conf.general.mensas = ConfigGeneral::default_mensas();
 		}
+		conf.shortcuts = shortcuts(&conf.aliases, &conf.groups).map_err(|e| format!("cannot load `{}`: {}", name, e))?;
 		Ok(conf)
 	}
 
+	/// Canteens named by aliases or groups that are not among the default ones,
+	/// these might be misspelled.
+	pub fn unlisted_shortcut_targets(&self) -> BTreeSet<&str> {
+		self.shortcuts.values()
+				.flat_map(|x| x.iter())
+				.filter(|x| !self.general.mensas.contains(x))
+				.map(String::as_str)
+				.collect()
+	}
+
+	/// Access rules for requests without a chat, e.g. inline queries.
+	pub fn is_user_allowed(&self, user: &tg::User) -> bool {
+		if self.allow.contains_user(Some(user)) {
+			true
+		} else if self.deny.contains_user(Some(user)) {
+			false
+		} else {
+			self.allow.is_empty()
+		}
+	}
+
 	pub fn is_allowed(&self, msg: &tg::Message) -> bool {
-		if self.allow.contains_user(msg.from.as_ref()) {
+		self.is_allowed_in(msg.from.as_ref(), &msg.chat)
+	}
+
+	pub fn is_allowed_in(&self, user: Option<&tg::User>, chat: &tg::Chat) -> bool {
+		if self.allow.contains_user(user) {
 			true
-		} else if self.deny.contains_user(msg.from.as_ref()) {
+		} else if self.deny.contains_user(user) {
 			false
-		} else if self.allow.chatids.contains(&msg.chat.id) {
+		} else if self.allow.chatids.contains(&chat.id) {
 			true
-		} else if self.deny.chatids.contains(&msg.chat.id) {
+		} else if self.deny.chatids.contains(&chat.id) {
 			false
 		} else if self.allow.is_empty() {
 			true
@@ -154,3 +351,23 @@ impl Config {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use super::shortcuts;
+
+	#[test]
+	fn shortcuts_must_not_collide() {
+		let mut aliases = BTreeMap::new();
+		aliases.insert(String::from("Alte-Mensa"), String::from("Alte Mensa"));
+		let mut groups = BTreeMap::new();
+		groups.insert(String::from("zelt"), vec![String::from("Zeltschlösschen")]);
+		let ok = shortcuts(&aliases, &groups).unwrap();
+		assert_eq!(ok.get("alte mensa"), Some(&vec![String::from("Alte Mensa")]));
+
+		groups.insert(String::from("alte mensa!"), vec![String::from("Alte Mensa"), String::from("Mensa Siedepunkt")]);
+		assert_eq!(shortcuts(&aliases, &groups), Err(String::from("'alte mensa!' is used by several aliases or groups")));
+	}
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+
+extern crate time;
+
+pub const WEEKDAYS: [&str; 7] = ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"];
+
+/// A calendar day, stored as the number of days since 1970-01-01.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Date {
+	days: i64
+}
+impl Date {
+	pub fn today() -> Date {
+		let now = time::now();
+		Date::from_ymd(now.tm_year + 1900, now.tm_mon as u32 + 1, now.tm_mday as u32).unwrap()
+	}
+
+	pub fn from_ymd(year: i32, month: u32, day: u32) -> Option<Date> {
+		if month < 1 || month > 12 || day < 1 || day > Date::days_in_month(year, month) {
+			return None;
+		}
+		// see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+		let y   = (if month <= 2 { year - 1 } else { year }) as i64;
+		let era = if y >= 0 { y } else { y - 399 } / 400;
+		let yoe = y - era * 400;
+		let mp  = (month as i64 + 9) % 12;
+		let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+		let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+		Some(Date { days: era * 146097 + doe - 719468 })
+	}
+
+	pub fn ymd(&self) -> (i32, u32, u32) {
+		// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+		let z   = self.days + 719468;
+		let era = if z >= 0 { z } else { z - 146096 } / 146097;
+		let doe = z - era * 146097;
+		let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+		let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+		let mp  = (5 * doy + 2) / 153;
+		let d   = doy - (153 * mp + 2) / 5 + 1;
+		let m   = if mp < 10 { mp + 3 } else { mp - 9 };
+		let y   = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+		(y as i32, m as u32, d as u32)
+	}
+
+	fn is_leap_year(year: i32) -> bool {
+		year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+	}
+
+	fn days_in_month(year: i32, month: u32) -> u32 {
+		match month {
+			2 => if Date::is_leap_year(year) { 29 } else { 28 },
+			4 | 6 | 9 | 11 => 30,
+			_ => 31
+		}
+	}
+
+	/// Day of the week, 0 is monday.
+	pub fn weekday(&self) -> u32 {
+		// 1970-01-01 was a thursday
+		((self.days + 3) % 7 + 7) as u32 % 7
+	}
+
+	pub fn weekday_name(&self) -> &'static str {
+		WEEKDAYS[self.weekday() as usize]
+	}
+
+	pub fn add_days(&self, n: i64) -> Date {
+		Date { days: self.days + n }
+	}
+
+	pub fn days_since(&self, other: &Date) -> i64 {
+		self.days - other.days
+	}
+
+	/// Monday of this date's week.
+	pub fn week_start(&self) -> Date {
+		self.add_days(-(self.weekday() as i64))
+	}
+
+	/// Parse `YYYY-MM-DD`.
+	pub fn parse_iso(s: &str) -> Option<Date> {
+		let parts = s.split("-").collect::<Vec<_>>();
+		if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+			return None;
+		}
+		match (parts[0].parse::<i32>(), parts[1].parse::<u32>(), parts[2].parse::<u32>()) {
+			(Ok(y), Ok(m), Ok(d)) => Date::from_ymd(y, m, d),
+			_                     => None
+		}
+	}
+}
+impl fmt::Display for Date {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let (y, m, d) = self.ymd();
+		write!(f, "{:04}-{:02}-{:02}", y, m, d)
+	}
+}
+
+pub fn time_cmp(x: (i32, i32, i32), y: (u8, u8, u8)) -> i32 {
+	let mut r = x.0 - y.0 as i32;
+	if r == 0 {
+		r = x.1 - y.1 as i32;
+		if r == 0 {
+			r = x.2 - y.2 as i32;
+		}
+	}
+	r
+}
+
+/// Current local time of day as (hours, minutes, seconds).
+pub fn now_time() -> (i32, i32, i32) {
+	let now = time::now();
+	(now.tm_hour, now.tm_min, now.tm_sec)
+}
+
+/// Parse `HH:MM[:SS]`, up to `24:00:00`.
+pub fn parse_time(s: &str) -> Result<(u8, u8, u8), String> {
+	let topts = s.split(":").map(|x| x.parse::<u8>()).collect::<Vec<_>>();
+	if 2 <= topts.len() && topts.len() <= 3 && topts.iter().all(|x| x.is_ok()) {
+		let mut t = topts.into_iter().map(|x| x.unwrap());
+		let     t = (t.next().unwrap(), t.next().unwrap(), t.next().unwrap_or(0));
+		if time_cmp((24, 0, 0), t.clone()) >= 0 && t.1 < 60 && t.2 < 60 {
+			return Ok(t);
+		}
+	}
+	Err(format!("invalid timestamp '{}'", s))
+}
+
+/// Interpret a single word of a search argument as a day relative to `today`.
+pub fn parse_day(word: &str, today: Date) -> Option<Date> {
+	let word = word.to_lowercase();
+	let word = word.trim_right_matches(|c| c == '.' || c == ',');
+	match word {
+		"heute"                      => return Some(today),
+		"morgen"                     => return Some(today.add_days(1)),
+		"übermorgen" | "uebermorgen" => return Some(today.add_days(2)),
+		_                            => {}
+	}
+	for (i, name) in WEEKDAYS.iter().enumerate() {
+		let name = name.to_lowercase();
+		if word == name || word == &name[..2] {
+			// next occurrence of that weekday, today included
+			let diff = (i as i64 - today.weekday() as i64 + 7) % 7;
+			return Some(today.add_days(diff));
+		}
+	}
+	Date::parse_iso(word)
+}
+
+/// Split the first word denoting a day off a search argument, returns that day
+/// and the remaining text.
+pub fn split_day(arg: &str, today: Date) -> (Option<Date>, String) {
+	let mut day  = None;
+	let mut rest = Vec::new();
+	for word in arg.split_whitespace() {
+		if day.is_none() {
+			day = parse_day(word, today);
+			if day.is_some() {
+				continue;
+			}
+		}
+		rest.push(word);
+	}
+	(day, rest.join(" "))
+}
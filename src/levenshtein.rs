@@ -1,18 +1,14 @@
 use std::cmp::max;
 use std::cmp::min;
-use std::cmp::Ordering;
 use std::fmt;
+use std::fmt::Write;
 use std::iter;
 
-trait VecReplace<T> {
-	fn replace(&mut self, i: usize, new: T) -> T;
-}
-impl<T> VecReplace<T> for Vec<T> {
-	fn replace(&mut self, i: usize, new: T) -> T {
-		self.push(new);
-		self.swap_remove(i)
-	}
-}
+extern crate unicode_normalization;
+
+use log::LogLevel;
+use self::unicode_normalization::UnicodeNormalization;
+use self::unicode_normalization::char::is_combining_mark;
 
 const KEEP:  u8 = 0;
 const SUBST: u8 = 1;
@@ -34,6 +30,7 @@ impl Operation {
 	}
 }
 
+/// Edit script with its distance, operations are packed into two bits each.
 pub struct Distance {
 	pub distance: usize,
 	words: Vec<u64>,
@@ -48,25 +45,21 @@ impl Distance {
 		}
 	}
 
-	fn next(&self, op: Operation) -> Distance {
+	fn push(&mut self, op: Operation) {
 		let opcode = match op {
 			Operation::Keep   => KEEP,
 			Operation::Subst  => SUBST,
 			Operation::Insert => INS,
 			Operation::Delete => DEL
 		} as u64;
-		let mut newwords = self.words.to_vec();
 		if self.len % 32 == 0 {
-			newwords.push(opcode);
+			self.words.push(opcode);
 		} else {
-			let n = newwords.len();
-			newwords[n - 1] |= opcode << (self.len % 32 * 2);
-		}
-		Distance {
-			distance: self.distance + !op.is_keep() as usize,
-			words:    newwords,
-			len:      self.len + 1
+			let n = self.words.len();
+			self.words[n - 1] |= opcode << (self.len % 32 * 2);
 		}
+		self.distance += !op.is_keep() as usize;
+		self.len += 1;
 	}
 
 	fn bits_to_operation(x: u64) -> Operation {
@@ -79,39 +72,13 @@ impl Distance {
 		}
 	}
 
-	fn get_last_operation(&self) -> Operation {
-		if self.len == 0 {
-			panic!("levenshtein distance empty");
-		}
-		let n  = self.len - 1;
-		let iw = n / 32;
-		let ib = n % 32;
-		Distance::bits_to_operation((self.words[iw] >> (ib * 2)) & 3)
-	}
-
-	fn iter(&self) -> Iterator {
+	pub fn iter(&self) -> Iterator {
 		Iterator {
 			lev: self,
 			i:   0
 		}
 	}
 }
-impl Ord for Distance {
-	fn cmp(&self, other: &Distance) -> Ordering {
-		self.distance.cmp(&other.distance)
-	}
-}
-impl PartialOrd for Distance {
-	fn partial_cmp(&self, other: &Distance) -> Option<Ordering> {
-		Some(self.cmp(other))
-	}
-}
-impl PartialEq for Distance {
-	fn eq(&self, other: &Distance) -> bool {
-		self.distance == other.distance && self.len == other.len && self.words == other.words
-	}
-}
-impl Eq for Distance {}
 impl fmt::Debug for Distance {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		for a in self.iter() {
@@ -144,101 +111,168 @@ impl<'a> iter::Iterator for Iterator<'a> {
 	}
 }
 
-pub fn levenshtein(a: &str, b: &str) -> Distance {
-	let mut lev: Vec<Distance> = Vec::with_capacity(b.len() + 1); // one more to use swap_remove
-
-	let n = {
-		lev.push(Distance::new());
-		let mut j = 0;
-		for _ in b.chars() {
-			let next = lev[j].next(Operation::Insert);
-			lev.push(next);
-			j += 1;
+/// Levenshtein distance between `a` and `b`, keeping only two rows of the
+/// matrix.
+pub fn distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut prev: Vec<usize> = (0..(b.len() + 1)).collect();
+	let mut cur = vec![0; b.len() + 1];
+	for (i, ca) in a.chars().enumerate() {
+		cur[0] = i + 1;
+		for (j, cb) in b.iter().enumerate() {
+			let subst = prev[j] + (ca != *cb) as usize;
+			cur[j + 1] = min(min(cur[j] + 1, prev[j + 1] + 1), subst);
 		}
-		j
-	};
+		::std::mem::swap(&mut prev, &mut cur);
+	}
+	prev[b.len()]
+}
 
-	lev.shrink_to_fit();
+/// Operations turning `a` into `b`, reconstructed from the full matrix.
+pub fn edit_script(a: &str, b: &str) -> Distance {
+	let a: Vec<char> = a.chars().collect();
+	let b: Vec<char> = b.chars().collect();
 
-	if cfg!(debug_assertions) {
-		eprint!("    ");
-		for cb in b.chars() {
-			eprint!("  {}", cb);
-		}
-		eprint!("\n   0");
-		for i in lev.iter().skip(1) {
-			eprint!(" \x1b[1;32m{:>2}\x1b[22;39m", i.distance);
+	let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+	for j in 0..(b.len() + 1) {
+		d[0][j] = j;
+	}
+	for i in 1..(a.len() + 1) {
+		d[i][0] = i;
+		for j in 1..(b.len() + 1) {
+			let subst = d[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+			d[i][j] = min(min(d[i][j - 1] + 1, d[i - 1][j] + 1), subst);
 		}
-		eprint!("\n");
 	}
 
-	for ca in a.chars() {
-		let     new   = lev[0].next(Operation::Delete);
-		let mut lev11 = lev.replace(0, new);
-
-		if cfg!(debug_assertions) {
-			eprint!("{} \x1b[1;31m{:>2}\x1b[22;39m", ca, lev[0].distance);
+	if log_enabled!(LogLevel::Trace) {
+		let mut s = String::from("\n    ");
+		for cb in b.iter() {
+			let _ = write!(s, "  {}", cb);
 		}
-
-		for (j, cb) in b.chars().enumerate() {
-			let j = j + 1;
-			let insert = lev[j - 1].next(Operation::Insert);
-			let delete = lev[  j  ].next(Operation::Delete);
-			let subst  = lev11.next(if ca == cb { Operation::Keep } else { Operation::Subst });
-			lev11 = lev.replace(j, min(min(insert, delete), subst));
-
-			if cfg!(debug_assertions) {
-				let color = match lev[j].get_last_operation() {
-					Operation::Keep   => "22;39",
-					Operation::Subst  => "1;33",
-					Operation::Insert => "1;32",
-					Operation::Delete => "1;31"
-				};
-				eprint!(" \x1b[{}m{:>2}\x1b[22;39m", color, lev[j].distance);
+		for (i, row) in d.iter().enumerate() {
+			s.push_str(if i == 0 { "\n " } else { "\n" });
+			if i > 0 {
+				s.push(a[i - 1]);
+			}
+			for x in row.iter() {
+				let _ = write!(s, " {:>2}", x);
 			}
 		}
+		trace!("{}", s);
+	}
 
-		if cfg!(debug_assertions) {
-			eprint!("\n");
+	// walk back from the end
+	let mut ops = Vec::with_capacity(max(a.len(), b.len()));
+	let (mut i, mut j) = (a.len(), b.len());
+	while i > 0 || j > 0 {
+		if i > 0 && j > 0 && d[i][j] == d[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize {
+			ops.push(if a[i - 1] == b[j - 1] { Operation::Keep } else { Operation::Subst });
+			i -= 1;
+			j -= 1;
+		} else if i > 0 && d[i][j] == d[i - 1][j] + 1 {
+			ops.push(Operation::Delete);
+			i -= 1;
+		} else {
+			ops.push(Operation::Insert);
+			j -= 1;
 		}
 	}
 
-	let lev = lev.remove(n);
-
-	if cfg!(debug_assertions) {
-		eprintln!("{} -> {} = {:?}", a, b, lev);
+	let mut lev = Distance::new();
+	for op in ops.into_iter().rev() {
+		lev.push(op);
 	}
-
-	return lev;
+	trace!("{} -> {} = {:?}", a.iter().collect::<String>(), b.iter().collect::<String>(), lev);
+	lev
 }
 
-fn find_best_word_match(mut rows: &mut Vec<Vec<usize>>) -> usize {
-	let mut max = 0;
-	let row = match rows.pop() {
-		None    => return max,
-		Some(r) => r
-	};
-	let numrows = rows.len();
-	for (i, d) in row.iter().enumerate() {
-		// remove column i
-		let mut col = Vec::with_capacity(numrows);
-		for row2 in rows.iter_mut() {
-			col.push(row2.remove(i));
-		}
-		// go deeper
-		let d2 = find_best_word_match(rows);
-		// reinsert column i
-		for (j, y) in col.into_iter().enumerate() {
-			rows[j].insert(i, y);
+/// Fold `s` for comparison: lowercase, umlauts transliterated (`ö` → `oe`,
+/// `ß` → `ss`), other accents and compatibility forms removed, and punctuation
+/// including hyphens turned into single spaces.
+pub fn normalize(s: &str) -> String {
+	let mut folded = String::with_capacity(s.len());
+	// compose first, so decomposed umlauts are transliterated as well
+	for c in s.nfc().flat_map(char::to_lowercase) {
+		match c {
+			'ä' => folded.push_str("ae"),
+			'ö' => folded.push_str("oe"),
+			'ü' => folded.push_str("ue"),
+			'ß' => folded.push_str("ss"),
+			c   => folded.push(c)
 		}
+	}
+	let folded: String = folded.nfkd()
+			.filter(|c| !is_combining_mark(*c))
+			.map(|c| if c.is_alphanumeric() { c } else { ' ' })
+			.collect();
+	folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
 
-		let d = d + d2;
-		if d > max {
-			max = d;
+/// Words of each string considered by `wordwise_levenshtein`, the assignment
+/// takes cubic time in this.
+const MAX_WORDS: usize = 32;
+
+/// Maximum sum of `rows[i][j]` choosing exactly one entry from every row and
+/// column of the square matrix `rows`, solved as an assignment problem with
+/// the Hungarian algorithm in O(n³).
+fn find_best_word_match(rows: &Vec<Vec<usize>>) -> usize {
+	let n = rows.len();
+	// potentials and matching are 1-based, index 0 is a sentinel
+	let mut u   = vec![0i64; n + 1];
+	let mut v   = vec![0i64; n + 1];
+	let mut p   = vec![0usize; n + 1]; // row matched to column j
+	let mut way = vec![0usize; n + 1];
+	let cost = |i: usize, j: usize| -(rows[i - 1][j - 1] as i64);
+
+	for i in 1..(n + 1) {
+		p[0] = i;
+		let mut j0 = 0;
+		let mut minv = vec![i64::max_value(); n + 1];
+		let mut used = vec![false; n + 1];
+		loop {
+			used[j0] = true;
+			let i0 = p[j0];
+			let mut delta = i64::max_value();
+			let mut j1 = 0;
+			for j in 1..(n + 1) {
+				if !used[j] {
+					let cur = cost(i0, j) - u[i0] - v[j];
+					if cur < minv[j] {
+						minv[j] = cur;
+						way[j]  = j0;
+					}
+					if minv[j] < delta {
+						delta = minv[j];
+						j1    = j;
+					}
+				}
+			}
+			for j in 0..(n + 1) {
+				if used[j] {
+					u[p[j]] += delta;
+					v[j]    -= delta;
+				} else {
+					minv[j] -= delta;
+				}
+			}
+			j0 = j1;
+			if p[j0] == 0 {
+				break;
+			}
+		}
+		// augment along the alternating path
+		loop {
+			let j1 = way[j0];
+			p[j0] = p[j1];
+			j0 = j1;
+			if j0 == 0 {
+				break;
+			}
 		}
 	}
-	rows.push(row);
-	max
+
+	(1..(n + 1)).map(|j| rows[p[j] - 1][j - 1]).sum()
 }
 
 pub fn wordwise_levenshtein(a: &str, b: &str) -> usize {
@@ -257,6 +291,7 @@ pub fn wordwise_levenshtein(a: &str, b: &str) -> usize {
 		x.split_whitespace()
 				.flat_map(|y| y.split(|c| c == '(' || c == ')' || c == ':'))
 				.filter_map(|z| if z.len() > 0 { Some(LenStr{len: z.chars().count(), s: z}) } else { None })
+				.take(MAX_WORDS)
 				.collect()
 	}
 
@@ -277,7 +312,7 @@ pub fn wordwise_levenshtein(a: &str, b: &str) -> usize {
 	for aword in words.a.iter() {
 		let mut row = Vec::with_capacity(maxwordnum);
 		for bword in words.b.iter() {
-			row.push(max(aword.len, bword.len) - levenshtein(aword.s, bword.s).distance);
+			row.push(max(aword.len, bword.len) - distance(aword.s, bword.s));
 		}
 
 		// anum > bnum -> add columns
@@ -297,5 +332,60 @@ pub fn wordwise_levenshtein(a: &str, b: &str) -> usize {
 		d.push(row);
 	}
 
-	find_best_word_match(&mut d)
+	find_best_word_match(&d)
+}
+
+/// How well the words of `query` are found in `name` from 0 to 1000, both
+/// should be normalized.
+pub fn similarity(query: &str, name: &str) -> usize {
+	let len: usize = query.split_whitespace().take(MAX_WORDS).map(|x| x.chars().count()).sum();
+	if len == 0 {
+		return 0;
+	}
+	min(1000, wordwise_levenshtein(query, name) * 1000 / len)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{find_best_word_match, normalize};
+
+	/// Maximum over all permutations, like the matching used to be computed.
+	fn brute_force(rows: &Vec<Vec<usize>>, used: &mut Vec<bool>, i: usize) -> usize {
+		if i == rows.len() {
+			return 0;
+		}
+		let mut best = 0;
+		for j in 0..rows.len() {
+			if !used[j] {
+				used[j] = true;
+				best = best.max(rows[i][j] + brute_force(rows, used, i + 1));
+				used[j] = false;
+			}
+		}
+		best
+	}
+
+	#[test]
+	fn best_word_match_is_optimal() {
+		let mut seed: u64 = 1;
+		let mut next = || {
+			seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+			(seed >> 33) as usize
+		};
+		for _ in 0..500 {
+			let n = next() % 7;
+			let rows: Vec<Vec<usize>> = (0..n).map(|_| (0..n).map(|_| next() % 12).collect()).collect();
+			assert_eq!(find_best_word_match(&rows), brute_force(&rows, &mut vec![false; n], 0), "{:?}", rows);
+		}
+	}
+
+	#[test]
+	fn normalize_folds_spellings() {
+		let folded = normalize("Zeltschlösschen");
+		assert_eq!(folded, "zeltschloesschen");
+		assert_eq!(normalize("zeltschloesschen"), folded);
+		assert_eq!(normalize("Zeltschlo\u{308}sschen"), folded);
+		assert_eq!(normalize("Alte Mensa – Mommsenstraße"), "alte mensa mommsenstrasse");
+		assert_eq!(normalize("  Mensa-Johannstadt!! (Uni) "), "mensa johannstadt uni");
+	}
 }
@@ -1,7 +1,10 @@
+use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::env;
+use std::sync::{Arc, Mutex};
 
+extern crate ctrlc;
 extern crate env_logger;
 #[macro_use]
 extern crate log;
@@ -13,56 +16,32 @@ extern crate serde;
 extern crate serde_derive;
 extern crate time;
 
-use select::predicate::Predicate;
-
+mod alerts;
+mod cache;
+mod commands;
 mod conf;
+mod date;
 mod levenshtein;
+mod menu;
+mod prefetch;
+mod ratelimit;
+mod source;
+mod storage;
+mod subscriptions;
 mod tg;
+mod webhook;
 
-fn time_cmp(x: (i32, i32, i32), y: (u8, u8, u8)) -> i32 {
-	let mut r = x.0 - y.0 as i32;
-	if r == 0 {
-		r = x.1 - y.1 as i32;
-		if r == 0 {
-			r = x.2 - y.2 as i32;
-		}
-	}
-	r
-}
-
-fn parse_tomorrow(tomorrow: &str) -> Result<(u8, u8, u8), String> {
-	let topts = tomorrow.split(":").map(|x| x.parse::<u8>()).collect::<Vec<_>>();
-	if 2 <= topts.len() && topts.len() <= 3 && topts.iter().all(|x| x.is_ok()) {
-		let mut t = topts.into_iter().map(|x| x.unwrap());
-		let     t = (t.next().unwrap(), t.next().unwrap(), t.next().unwrap_or(0));
-		if time_cmp((24, 0, 0), t.clone()) >= 0 {
-			return Ok(t);
-		}
-	}
-	Err(format!("invalid timestamp '{}'", tomorrow))
-}
-
-fn strip_white(s: &str) -> String {
-	let words = s.split_whitespace();
-	let mut s = String::with_capacity(s.len());
-	for w in words.into_iter() {
-		s.push_str(w);
-		s.push_str(" ");
-	}
-	let n = s.len();
-	if n > 0 {
-		s.truncate(n - 1);
-		s.shrink_to_fit();
-	}
-	s
-}
+use date::Date;
+use menu::{Meal, Menu};
+use cache::MenuCache;
+use commands::CommandInfo;
 
-fn get_text_content(x: &select::node::Node) -> String {
-	strip_white(&x.children().filter_map(|x| x.as_text()).collect::<String>())
-}
+/// Minimum difference of similarities to tell imperfect matches apart.
+const AMBIGUITY: usize = 50;
 
 #[derive(Eq)]
 struct MensaMatch {
+	/// from 0 to 1000
 	similarity: usize,
 	name:       String
 }
@@ -86,114 +65,112 @@ impl PartialEq for MensaMatch {
 	}
 }
 
-fn parse_menu(doc: select::document::Document, arg: Option<&str>, mensas: &Vec<String>) -> BTreeMap<MensaMatch, Vec<String>> {
-	let mut menu: BTreeMap<MensaMatch, Vec<String>> = BTreeMap::new();
-	for table in doc.find(select::predicate::Name("table").and(select::predicate::Class("speiseplan"))) {
-		let mut children = table.children();
-
-		// find thead
-		let mut theadopt: Option<select::node::Node>;
-		loop {
-			theadopt = children.next();
-			let thead = match theadopt {
-				None    => break,
-				Some(x) => x
-			};
-			match thead.name() {
-				None    => continue,
-				Some(x) => if x != "thead" {
-					continue
-				}
-			};
-			break;
-		}
-		let thead = match theadopt {
-			None    => continue,
-			Some(x) => x
-		};
-
-		// get mensa name
-		let th = match thead.find(select::predicate::Name("th")).next() {
-			None    => continue,
-			Some(x) => x
-		};
-		let mut mensa = get_text_content(&th);
-		if &mensa[..9] == "Angebote " {
-			mensa = String::from(&mensa[9..]);
-		}
+fn match_menu(menu: Menu, arg: Option<&str>, mensas: &Vec<String>, shortcuts: &BTreeMap<String, Vec<String>>) -> BTreeMap<MensaMatch, Vec<Meal>> {
+	let arg = arg.map(levenshtein::normalize);
+	// aliases and groups name canteens exactly
+	let (arg, mensas) = match arg.as_ref().and_then(|x| shortcuts.get(x)) {
+		None    => (arg, mensas),
+		Some(x) => (None, x)
+	};
+	let mut matches = BTreeMap::new();
+	for (mensa, meals) in menu.into_iter() {
 		let mensa = MensaMatch{
 			similarity: match arg {
-				None    => if mensas.iter().any(|x| x == &mensa) { 0 } else { continue; },
-				Some(x) => levenshtein::wordwise_levenshtein(x, &mensa.to_lowercase())
+				None        => if mensas.iter().any(|x| x == &mensa) { 1000 } else { continue; },
+				Some(ref x) => levenshtein::similarity(x, &levenshtein::normalize(&mensa))
 			},
 			name: mensa
 		};
-
-		// get meals
-		let tbodies = children
-				.filter(|&x| x.name().map_or(false, |y| y == "tbody"))
-				.take(4)
-				.enumerate()
-				.filter_map(|(i, x)| if i == 0 || i == 3 { Some(x) } else { None });
-		let trs = tbodies
-				.flat_map(|x| x.children())
-				.filter(|&x| x.name().map_or(false, |y| y == "tr"));
-		let mut meals = Vec::new();
-		for tr in trs {
-			let td = match tr.find(select::predicate::Name("td").and(select::predicate::Class("text"))).next() {
-				None    => continue,
-				Some(x) => x
-			};
-			let a = match td.find(select::predicate::Name("a")).next() {
-				None    => continue,
-				Some(x) => x
-			};
-			let txt = get_text_content(&a);
-			meals.push(txt);
-		}
-
-		if meals.is_empty() {
-			continue;
-		}
-
-		menu.insert(mensa, meals);
+		matches.insert(mensa, meals);
 	}
+	matches
+}
 
-	menu
+/// Settings of a chat with the configuration file as fallback.
+struct ChatConfig<'a> {
+	mensas:    Vec<String>,
+	tomorrow:  (u8, u8, u8),
+	notify:    bool,
+	/// minimum similarity of canteens from 0 to 1000
+	threshold: usize,
+	shortcuts: &'a BTreeMap<String, Vec<String>>
+}
+impl<'a> ChatConfig<'a> {
+	fn get(conf: &'a conf::Config, tomorrow: (u8, u8, u8), storage: &Mutex<storage::Storage>, chat: i64) -> ChatConfig<'a> {
+		let mut c = ChatConfig {
+			mensas:    conf.general.mensas.clone(),
+			tomorrow:  tomorrow,
+			notify:    conf.general.notify,
+			threshold: (conf.general.threshold * 1000.0) as usize,
+			shortcuts: &conf.shortcuts
+		};
+		match storage.lock().unwrap().state.chats.get(&chat) {
+			None    => {},
+			Some(s) => {
+				match s.mensas {
+					None        => {},
+					Some(ref x) => c.mensas = x.clone()
+				};
+				match s.tomorrow.as_ref().map(|x| date::parse_time(x)) {
+					Some(Ok(x)) => c.tomorrow = x,
+					_           => {}
+				};
+				match s.notify {
+					None    => {},
+					Some(x) => c.notify = x
+				};
+			}
+		};
+		c
+	}
 }
 
-fn get_menu_url(tomorrow: (u8, u8, u8)) -> &'static str {
-	let now  = time::now();
-	let vnow = (now.tm_hour, now.tm_min, now.tm_sec);
-	if time_cmp(vnow, tomorrow) >= 0 {
-		"https://www.studentenwerk-dresden.de/mensen/speiseplan/morgen.html"
+fn get_default_day(today: Date, tomorrow: (u8, u8, u8)) -> Date {
+	if date::time_cmp(date::now_time(), tomorrow) >= 0 {
+		today.add_days(1)
 	} else {
-		"https://www.studentenwerk-dresden.de/mensen/speiseplan/"
+		today
 	}
 }
 
-fn fetch_menu(url: &str, arg: Option<&str>, mensas: &Vec<String>) -> Result<BTreeMap<MensaMatch, Vec<String>>, String> {
-	let resp = match reqwest::get(url) {
-		Err(e) => return Err(format!("{}", e)),
-		Ok(r)  => r
-	};
-	if resp.status() != reqwest::StatusCode::Ok {
-		return Err(format!("HTTP error {}", resp.status()));
-	}
+/// Returns the matching menus and, if they are outdated, when they were fetched.
+fn fetch_menu(cache: &MenuCache, day: Date, arg: Option<&str>, chatconf: &ChatConfig) -> Result<(BTreeMap<MensaMatch, Vec<Meal>>, Option<String>), String> {
+	info!("fetching menu for {}", day);
+	let cached = cache.get(day)?;
+	let stand  = if cached.stale { Some(cached.timestamp()) } else { None };
+	Ok((match_menu(cached.menu, arg, &chatconf.mensas, chatconf.shortcuts), stand))
+}
 
-	let doc = match select::document::Document::from_read(resp) {
-		Err(e) => return Err(format!("{}", e)),
-		Ok(d)  => d
+/// Suggest the closest canteens if none matches well enough or an imperfect
+/// match is hardly better than the next one, `threshold` from 0 to 1000.
+fn suggest_mensas(menu: &BTreeMap<MensaMatch, Vec<Meal>>, threshold: usize) -> Option<String> {
+	let best = match menu.keys().next() {
+		None    => return None,
+		Some(x) => x.similarity
 	};
+	let ambiguous = best < 1000 && menu.keys()
+			.find(|x| x.similarity < best)
+			.map_or(false, |x| x.similarity + AMBIGUITY > best);
+	if best >= threshold && !ambiguous {
+		return None;
+	}
 
-	Ok(parse_menu(doc, arg, mensas))
+	let names = menu.keys()
+			.filter(|x| x.similarity > 0)
+			.take(3)
+			.map(|x| x.name.as_str())
+			.collect::<Vec<_>>();
+	Some(if names.is_empty() {
+		String::from("Keine Mensa gefunden!")
+	} else {
+		format!("Keine Mensa gefunden – meintest du: {}?", names.join(", "))
+	})
 }
 
-fn create_menu_message(menu: &BTreeMap<MensaMatch, Vec<String>>) -> String {
+fn create_menu_message(menu: &BTreeMap<MensaMatch, Vec<Meal>>) -> String {
 	let mut s = String::new();
 	let mut similarity = None;
 	for (mensa, meals) in menu.iter() {
-		let oldlen = s.len();
 		match similarity {
 			None       => { similarity = Some(mensa.similarity); },
 			Some(prev) => if prev > mensa.similarity { break; }
@@ -201,14 +178,9 @@ fn create_menu_message(menu: &BTreeMap<MensaMatch, Vec<String>>) -> String {
 		s.push_str(&mensa.name);
 		for meal in meals {
 			s.push_str("\n * ");
-			s.push_str(meal);
+			s.push_str(&meal.to_string());
 		}
 		s.push_str("\n\n");
-		if s.len() > 4093 {
-			s.truncate(oldlen);
-			s.push_str("...\n\n");
-			break;
-		}
 	}
 	let n = s.len();
 	if n > 0 {
@@ -217,21 +189,465 @@ fn create_menu_message(menu: &BTreeMap<MensaMatch, Vec<String>>) -> String {
 	s
 }
 
-fn make_menu_text(msg: &tg::Message, arg: Option<&str>, mensas: &Vec<String>, tomorrow: (u8, u8, u8)) -> tg::OutgoingText {
-	let url = get_menu_url(tomorrow);
-	info!("fetching menu");
-	let txt = match fetch_menu(url, arg, mensas) {
+/// Callback data of menu navigation buttons, Telegram allows at most 64 bytes.
+fn menu_callback_data(day: Date, arg: Option<&str>) -> Option<String> {
+	let data = format!("m {} {}", day, arg.unwrap_or(""));
+	let data = data.trim_right();
+	if data.len() <= 64 { Some(String::from(data)) } else { None }
+}
+
+fn parse_menu_callback_data(data: &str) -> Option<(Date, Option<String>)> {
+	let mut parts = data.splitn(3, ' ');
+	if parts.next() != Some("m") {
+		return None;
+	}
+	let day = match parts.next().and_then(Date::parse_iso) {
+		None    => return None,
+		Some(d) => d
+	};
+	let arg = parts.next().map(str::trim).filter(|x| !x.is_empty()).map(String::from);
+	Some((day, arg))
+}
+
+fn create_menu_keyboard(day: Date, arg: Option<&str>, chatconf: &ChatConfig) -> tg::InlineKeyboardMarkup {
+	let mut keyboard = tg::InlineKeyboardMarkup::new();
+
+	let mut row = Vec::new();
+	for &(label, d) in [("◀ {}", day.add_days(-1)), ("{} ▶", day.add_days(1))].iter() {
+		match menu_callback_data(d, arg) {
+			None       => {},
+			Some(data) => row.push(tg::InlineKeyboardButton::callback(label.replace("{}", &d.weekday_name()[..2]), data))
+		}
+	}
+	keyboard.inline_keyboard.push(row);
+
+	// other canteens
+	let mut row = Vec::new();
+	if arg.is_some() {
+		match menu_callback_data(day, None) {
+			None       => {},
+			Some(data) => row.push(tg::InlineKeyboardButton::callback("Standard", data))
+		}
+	}
+	for mensa in chatconf.mensas.iter() {
+		let name = mensa.to_lowercase();
+		if arg == Some(name.as_str()) {
+			continue;
+		}
+		match menu_callback_data(day, Some(name.as_str())) {
+			None       => {},
+			Some(data) => row.push(tg::InlineKeyboardButton::callback(mensa.as_str(), data))
+		}
+	}
+	if !row.is_empty() {
+		keyboard.inline_keyboard.push(row);
+	}
+
+	keyboard
+}
+
+/// Menu of `day` for the canteens matching `arg`, the day is mentioned if
+/// `show_day` is set.
+fn create_menu_text(cache: &MenuCache, day: Date, show_day: bool, arg: Option<&str>, chatconf: &ChatConfig) -> String {
+	let mut txt = if show_day {
+		format!("{}, {}\n\n", day.weekday_name(), day)
+	} else {
+		String::new()
+	};
+
+	match fetch_menu(cache, day, arg, chatconf) {
 		Err(e) => {
 			error!("cannot fetch menu: {}", e);
-			format!("Speiseplan konnte nicht abgerufen werden!\n{}", url)
+			txt.push_str(&format!("Speiseplan konnte nicht abgerufen werden!\n{}", cache.source().url(day)));
 		},
-		Ok(menu) => create_menu_message(&menu)
+		Ok((menu, stand)) => {
+			if menu.is_empty() {
+				txt.push_str("Für diesen Tag gibt es keinen Speiseplan!");
+			} else {
+				match suggest_mensas(&menu, chatconf.threshold) {
+					None    => txt.push_str(&create_menu_message(&menu)),
+					Some(x) => txt.push_str(&x)
+				}
+			}
+			match stand {
+				None    => {},
+				Some(x) => txt.push_str(&format!("\n\nStand: {}", x))
+			}
+		}
+	};
+	txt
+}
+
+fn make_menu_text(msg: &tg::Message, cache: &MenuCache, arg: Option<&str>, chatconf: &ChatConfig) -> tg::OutgoingText {
+	let today = Date::today();
+	let (day, arg) = match arg {
+		None    => (None, None),
+		Some(x) => {
+			let (day, rest) = date::split_day(x, today);
+			(day, if rest.is_empty() { None } else { Some(rest) })
+		}
+	};
+	let show_day = day.is_some();
+	let day = day.unwrap_or_else(|| get_default_day(today, chatconf.tomorrow));
+	let arg = arg.as_ref().map(String::as_str);
+
+	let txt = create_menu_text(cache, day, show_day, arg, chatconf);
+	// only a single message can be replaced when navigating
+	let navigable = txt.encode_utf16().count() <= tg::MAX_TEXT_LENGTH;
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = !chatconf.notify;
+	if navigable {
+		re.reply_markup = Some(create_menu_keyboard(day, arg, chatconf));
+	}
+	re
+}
+
+/// Replace a menu by the one a navigation button points to, `None` if nothing
+/// changes. Only one message can be edited, so a long menu is shortened.
+fn make_menu_edit(msg: &tg::Message, data: &str, cache: &MenuCache, chatconf: &ChatConfig) -> Option<tg::EditText> {
+	let (day, arg) = match parse_menu_callback_data(data) {
+		None    => return None,
+		Some(x) => x
+	};
+	let arg = arg.as_ref().map(String::as_str);
+	let txt = tg::truncate_text(&create_menu_text(cache, day, true, arg, chatconf), "\n…");
+	if msg.text.as_ref() == Some(&txt) {
+		return None;
+	}
+	let mut edit = msg.edit_text(txt);
+	edit.reply_markup = Some(create_menu_keyboard(day, arg, chatconf));
+	Some(edit)
+}
+
+fn make_week_text(msg: &tg::Message, cache: &MenuCache, arg: Option<&str>, chatconf: &ChatConfig) -> tg::OutgoingText {
+	let today = Date::today();
+	let mut day = get_default_day(today, chatconf.tomorrow);
+	if day.weekday() >= 5 {
+		// show next week on weekends
+		day = day.add_days(7);
+	}
+	let monday = day.week_start();
+
+	let mut txt = String::new();
+	let mut stale_stand = None;
+	for i in 0..6 {
+		let day = monday.add_days(i);
+		let menu = match fetch_menu(cache, day, arg, chatconf) {
+			Err(e) => {
+				// the other days might still be available
+				error!("cannot fetch menu: {}", e);
+				txt.push_str(&format!("{}, {}\nSpeiseplan konnte nicht abgerufen werden!\n{}\n\n",
+						day.weekday_name(), day, cache.source().url(day)));
+				continue;
+			},
+			Ok((menu, stand)) => {
+				if stale_stand.is_none() {
+					stale_stand = stand;
+				}
+				menu
+			}
+		};
+		if menu.is_empty() {
+			continue;
+		}
+		match suggest_mensas(&menu, chatconf.threshold) {
+			None    => {},
+			Some(x) => {
+				let mut re = msg.reply_text(x);
+				re.disable_notification = !chatconf.notify;
+				return re;
+			}
+		}
+		txt.push_str(&format!("{}, {}\n", day.weekday_name(), day));
+		txt.push_str(&create_menu_message(&menu));
+		txt.push_str("\n\n");
+	}
+	let n = txt.len();
+	if n > 0 {
+		txt.truncate(n - 2);
+	} else {
+		txt.push_str("Für diese Woche gibt es keinen Speiseplan!");
+	}
+	match stale_stand {
+		None    => {},
+		Some(x) => txt.push_str(&format!("\n\nStand: {}", x))
+	}
+
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = !chatconf.notify;
+	re
+}
+
+fn make_inline_answer(query: &tg::InlineQuery, cache: &MenuCache, chatconf: &ChatConfig) -> tg::InlineQueryAnswer {
+	let mut answer = tg::InlineQueryAnswer::new(query.id.clone());
+
+	let today = Date::today();
+	let arg = query.query.trim().to_lowercase();
+	let (day, arg) = date::split_day(&arg, today);
+	let header = match day {
+		None    => String::new(),
+		Some(d) => format!("{}, {}\n\n", d.weekday_name(), d)
+	};
+	let day = day.unwrap_or_else(|| get_default_day(today, chatconf.tomorrow));
+	let arg = if arg.is_empty() { None } else { Some(arg.as_str()) };
+
+	let menu = match fetch_menu(cache, day, arg, chatconf) {
+		Err(e) => {
+			error!("cannot fetch menu: {}", e);
+			return answer;
+		},
+		Ok((menu, _)) => menu
+	};
+
+	// best matching canteens first
+	for (i, (mensa, meals)) in menu.into_iter().filter(|x| x.0.similarity >= chatconf.threshold).take(10).enumerate() {
+		let description = meals.iter().map(|x| x.name.as_str()).collect::<Vec<_>>().join(", ");
+		let mut single = BTreeMap::new();
+		let name = mensa.name.clone();
+		single.insert(mensa, meals);
+		// a result can only be a single message
+		let txt = format!("{}{}", header, create_menu_message(&single));
+		let txt = tg::truncate_text(&txt, "\n…");
+		let mut result = tg::InlineQueryResultArticle::new(format!("{}-{}", day, i), name, txt);
+		result.description = Some(description);
+		answer.results.push(result);
+	}
+	answer
+}
+
+fn make_subscribe_text(msg: &tg::Message, storage: &Mutex<storage::Storage>, arg: Option<&str>) -> tg::OutgoingText {
+	let txt = match subscriptions::parse_args(arg) {
+		Err(e) => {
+			info!("invalid subscription: {}", e);
+			String::from("Ungültige Uhrzeit!\n/subscribe [HH:MM] [<Mensa>]")
+		},
+		Ok(sub) => {
+			let txt = match sub.query {
+				None        => format!("Der Speiseplan wird werktags um {} Uhr gesendet.", sub.time),
+				Some(ref q) => format!("Der Speiseplan für \"{}\" wird werktags um {} Uhr gesendet.", q, sub.time)
+			};
+			let mut storage = storage.lock().unwrap();
+			storage.state.subscriptions.insert(msg.chat.id, sub);
+			match storage.save() {
+				Err(e) => {
+					error!("cannot save state: {}", e);
+					String::from("Abonnement konnte nicht gespeichert werden!")
+				},
+				Ok(_) => txt
+			}
+		}
+	};
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = true;
+	re
+}
+
+fn make_unsubscribe_text(msg: &tg::Message, storage: &Mutex<storage::Storage>) -> tg::OutgoingText {
+	let mut storage = storage.lock().unwrap();
+	let txt = match storage.state.subscriptions.remove(&msg.chat.id) {
+		None    => String::from("Es besteht kein Abonnement."),
+		Some(_) => match storage.save() {
+			Err(e) => {
+				error!("cannot save state: {}", e);
+				String::from("Abonnement konnte nicht gespeichert werden!")
+			},
+			Ok(_) => String::from("Abonnement beendet.")
+		}
+	};
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = true;
+	re
+}
+
+fn make_alert_text(msg: &tg::Message, storage: &Mutex<storage::Storage>, arg: Option<&str>) -> tg::OutgoingText {
+	let usage = "/alert add <Stichwort>\n/alert del <Stichwort>\n/alert list";
+	let user = match msg.from {
+		None        => return msg.reply_text(usage),
+		Some(ref u) => u.id
+	};
+	let mut words = arg.unwrap_or("list").splitn(2, char::is_whitespace);
+	let action  = words.next().unwrap_or("");
+	let keyword = words.next().map(str::trim).unwrap_or("");
+
+	let mut storage = storage.lock().unwrap();
+	let (txt, changed) = match (action, keyword.is_empty()) {
+		("add", false) => alerts::modify(&mut storage, user, |a| if a.keywords.iter().any(|x| x == keyword) {
+			(format!("\"{}\" ist bereits gespeichert.", keyword), false)
+		} else {
+			a.keywords.push(String::from(keyword));
+			(format!("Sobald \"{}\" auf dem Speiseplan steht, wirst du privat benachrichtigt, \
+					dazu musst du den Bot einmal privat gestartet haben.", keyword), true)
+		}),
+		("del", false) => alerts::modify(&mut storage, user, |a| {
+			let n = a.keywords.len();
+			a.keywords.retain(|x| x != keyword);
+			if a.keywords.len() < n {
+				(format!("\"{}\" gelöscht.", keyword), true)
+			} else {
+				(format!("\"{}\" ist nicht gespeichert.", keyword), false)
+			}
+		}),
+		("list", true) => (match storage.state.alerts.get(&user) {
+			None    => String::from("Keine Stichwörter gespeichert."),
+			Some(a) => format!("Stichwörter:\n * {}", a.keywords.join("\n * "))
+		}, false),
+		_ => (String::from(usage), false)
+	};
+	let txt = if !changed {
+		txt
+	} else {
+		match storage.save() {
+			Err(e) => {
+				error!("cannot save state: {}", e);
+				String::from("Stichwörter konnten nicht gespeichert werden!")
+			},
+			Ok(_) => txt
+		}
+	};
+
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = true;
+	re
+}
+
+fn escape_html(s: &str) -> String {
+	s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;")
+}
+
+fn make_settings_text(msg: &tg::Message, conf: &conf::Config, storage: &Mutex<storage::Storage>) -> tg::OutgoingText {
+	let settings = storage.lock().unwrap().state.chats.get(&msg.chat.id).cloned().unwrap_or_default();
+
+	let mut txt = String::from("<b>Einstellungen</b>\n/setmensa: ");
+	let (mensas, default) = match settings.mensas {
+		None        => (&conf.general.mensas, true),
+		Some(ref x) => (x, false)
+	};
+	txt.push_str(&mensas.iter().map(|x| format!("<code>{}</code>", escape_html(x))).collect::<Vec<_>>().join(", "));
+	if default {
+		txt.push_str(" (Standard)");
+	}
+
+	txt.push_str("\n/settomorrow: <code>");
+	match settings.tomorrow {
+		None        => {
+			txt.push_str(&escape_html(&conf.general.tomorrow));
+			txt.push_str("</code> (Standard)");
+		},
+		Some(ref x) => {
+			txt.push_str(&escape_html(x));
+			txt.push_str("</code>");
+		}
+	};
+
+	txt.push_str("\n/setnotify: ");
+	txt.push_str(if settings.notify.unwrap_or(conf.general.notify) { "an" } else { "aus" });
+	if settings.notify.is_none() {
+		txt.push_str(" (Standard)");
+	}
+
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = true;
+	re.parse_mode = Some(String::from("html"));
+	re
+}
+
+/// Apply `f` on the chat's settings and save them, `f` returns the reply or
+/// `Err` with a reply if nothing has changed.
+fn modify_settings<F>(msg: &tg::Message, storage: &Mutex<storage::Storage>, f: F) -> tg::OutgoingText
+		where F: FnOnce(&mut storage::ChatSettings) -> Result<String, String> {
+	let mut storage = storage.lock().unwrap();
+	let txt = {
+		let settings = storage.state.chats.entry(msg.chat.id).or_insert_with(storage::ChatSettings::default);
+		f(settings)
+	};
+	let txt = match txt {
+		Err(txt) => txt,
+		Ok(txt)  => {
+			let empty = storage.state.chats.get(&msg.chat.id)
+					.map_or(false, |x| x.mensas.is_none() && x.tomorrow.is_none() && x.notify.is_none());
+			if empty {
+				storage.state.chats.remove(&msg.chat.id);
+			}
+			match storage.save() {
+				Err(e) => {
+					error!("cannot save state: {}", e);
+					String::from("Einstellungen konnten nicht gespeichert werden!")
+				},
+				Ok(_) => txt
+			}
+		}
 	};
 	let mut re = msg.reply_text(txt);
 	re.disable_notification = true;
 	re
 }
 
+fn make_setmensa_text(msg: &tg::Message, cache: &MenuCache, storage: &Mutex<storage::Storage>, arg: Option<&str>) -> tg::OutgoingText {
+	let arg = match arg {
+		None    => return modify_settings(msg, storage, |s| {
+			s.mensas = None;
+			Ok(String::from("Standardmensen werden verwendet."))
+		}),
+		Some(x) => x
+	};
+
+	// resolve to the exact names, arguments are lowercase
+	let canteens = match cache.source().canteens() {
+		Err(e) => {
+			error!("cannot fetch canteens: {}", e);
+			return msg.reply_text("Mensen konnten nicht abgerufen werden!");
+		},
+		Ok(x) => x
+	};
+	let mut mensas = Vec::new();
+	for name in arg.split(|c| c == ',' || c == ';').map(str::trim).filter(|x| !x.is_empty()) {
+		match canteens.iter().find(|x| x.to_lowercase() == name) {
+			None    => return msg.reply_text(format!("Unbekannte Mensa \"{}\"!\nBekannt sind: {}", name, canteens.join(", "))),
+			Some(x) => mensas.push(x.clone())
+		}
+	}
+
+	modify_settings(msg, storage, |s| {
+		let txt = format!("Standardmensen: {}", mensas.join(", "));
+		s.mensas = Some(mensas);
+		Ok(txt)
+	})
+}
+
+fn make_settomorrow_text(msg: &tg::Message, storage: &Mutex<storage::Storage>, arg: Option<&str>) -> tg::OutgoingText {
+	modify_settings(msg, storage, |s| match arg {
+		None    => {
+			s.tomorrow = None;
+			Ok(String::from("Standardzeit für den nächsten Tag wird verwendet."))
+		},
+		Some(x) => match date::parse_time(x) {
+			Err(_) => Err(String::from("Ungültige Uhrzeit!\n/settomorrow [HH:MM[:SS]]")),
+			Ok(_)  => {
+				s.tomorrow = Some(String::from(x));
+				Ok(format!("Ab {} wird der Speiseplan des nächsten Tages gesendet.", x))
+			}
+		}
+	})
+}
+
+fn make_setnotify_text(msg: &tg::Message, storage: &Mutex<storage::Storage>, arg: Option<&str>) -> tg::OutgoingText {
+	modify_settings(msg, storage, |s| match arg {
+		None         => {
+			s.notify = None;
+			Ok(String::from("Standardbenachrichtigung wird verwendet."))
+		},
+		Some("an")  | Some("on")  => {
+			s.notify = Some(true);
+			Ok(String::from("Speisepläne werden mit Benachrichtigung gesendet."))
+		},
+		Some("aus") | Some("off") => {
+			s.notify = Some(false);
+			Ok(String::from("Speisepläne werden ohne Benachrichtigung gesendet."))
+		},
+		Some(_) => Err(String::from("/setnotify [an|aus]"))
+	})
+}
+
 fn make_about_text(msg: &tg::Message, conf: &conf::Config) -> tg::OutgoingText {
 	let mut txt = String::from("<b>Copyright 2017-2018 Schnusch</b>
 https://www.github.com/schnusch/mensabot/
@@ -246,7 +662,7 @@ access: ");
 	txt.push_str("\ndefault: ");
 	for mensa in conf.general.mensas.iter() {
 		txt.push_str("<code>");
-		txt.push_str(&mensa.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;"));
+		txt.push_str(&escape_html(mensa));
 		txt.push_str("</code>, ");
 	}
 	if !conf.general.mensas.is_empty() {
@@ -260,7 +676,7 @@ access: ");
 		txt.push_str("\npatterns:");
 		for pat in conf.general.patterns.iter() {
 			txt.push_str("\n <code>");
-			txt.push_str(&pat.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;"));
+			txt.push_str(&escape_html(pat));
 			txt.push_str("</code>");
 		}
 	}
@@ -271,6 +687,379 @@ access: ");
 	re
 }
 
+fn make_help_text<'a, I>(msg: &tg::Message, commands: I) -> tg::OutgoingText where I: Iterator<Item=&'a CommandInfo> {
+	let mut txt = String::from("<b>Befehle</b>");
+	for cmd in commands {
+		txt.push_str("\n/");
+		txt.push_str(cmd.name);
+		if !cmd.args.is_empty() {
+			txt.push_str(" <code>");
+			txt.push_str(&escape_html(cmd.args));
+			txt.push_str("</code>");
+		}
+		txt.push_str("\n    ");
+		txt.push_str(&escape_html(cmd.description));
+	}
+
+	let mut re = msg.reply_text(txt);
+	re.disable_notification = true;
+	re.parse_mode = Some(String::from("html"));
+	re
+}
+
+/// Reply to `/start`, deep links like `?start=morgen_alte_mensa` pass a day and
+/// canteen separated by underscores.
+fn make_start_text(bot: &Bot, msg: &tg::Message, payload: Option<&str>) -> tg::OutgoingText {
+	match payload {
+		None    => {
+			let mut re = make_help_text(msg, bot.router.commands());
+			re.text = format!("Hallo! Ich sende dir die Speisepläne der Mensen.\n\n{}", re.text);
+			re
+		},
+		Some(x) => {
+			let arg = x.replace('_', " ");
+			make_menu_text(msg, &bot.cache, Some(arg.trim()), &bot.chat_config(msg.chat.id))
+		}
+	}
+}
+
+fn create_router() -> commands::Router {
+	let mut router = commands::Router::new();
+	router.register(CommandInfo {
+		name:           "mensa",
+		args:           "[<Tag>] [<Mensa>]",
+		description:    "Speiseplan anzeigen",
+		description_en: "Show the menu",
+		private:        false
+	}, "menu", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_menu_text(msg, &bot.cache, arg, &bot.chat_config(msg.chat.id))
+	});
+	router.register(CommandInfo {
+		name:           "woche",
+		args:           "[<Mensa>]",
+		description:    "Speiseplan der Woche anzeigen",
+		description_en: "Show the menu of the week",
+		private:        false
+	}, "weekly menu", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_week_text(msg, &bot.cache, arg, &bot.chat_config(msg.chat.id))
+	});
+	router.register(CommandInfo {
+		name:           "subscribe",
+		args:           "[<HH:MM>] [<Mensa>]",
+		description:    "Speiseplan werktags senden",
+		description_en: "Send the menu every weekday",
+		private:        false
+	}, "subscription reply", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_subscribe_text(msg, &bot.storage, arg)
+	});
+	router.register(CommandInfo {
+		name:           "unsubscribe",
+		args:           "",
+		description:    "Speiseplan nicht mehr senden",
+		description_en: "Stop sending the menu",
+		private:        false
+	}, "subscription reply", |bot: &Bot, msg: &tg::Message, _: Option<&str>| {
+		make_unsubscribe_text(msg, &bot.storage)
+	});
+	router.register(CommandInfo {
+		name:           "alert",
+		args:           "add|del <Stichwort>, list",
+		description:    "Bei bestimmten Gerichten benachrichtigen",
+		description_en: "Get notified about certain meals",
+		private:        true
+	}, "alert reply", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_alert_text(msg, &bot.storage, arg)
+	});
+	router.register(CommandInfo {
+		name:           "settings",
+		args:           "",
+		description:    "Einstellungen anzeigen",
+		description_en: "Show the settings",
+		private:        false
+	}, "settings", |bot: &Bot, msg: &tg::Message, _: Option<&str>| {
+		make_settings_text(msg, &bot.conf, &bot.storage)
+	});
+	router.register(CommandInfo {
+		name:           "setmensa",
+		args:           "[<Mensa>[, <Mensa>...]]",
+		description:    "Standardmensen festlegen",
+		description_en: "Set the default canteens",
+		private:        false
+	}, "settings reply", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_setmensa_text(msg, &bot.cache, &bot.storage, arg)
+	});
+	router.register(CommandInfo {
+		name:           "settomorrow",
+		args:           "[<HH:MM[:SS]>]",
+		description:    "Uhrzeit für den Speiseplan von morgen festlegen",
+		description_en: "Set the time from which on tomorrow's menu is shown",
+		private:        false
+	}, "settings reply", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_settomorrow_text(msg, &bot.storage, arg)
+	});
+	router.register(CommandInfo {
+		name:           "setnotify",
+		args:           "[an|aus]",
+		description:    "Benachrichtigungen ein- oder ausschalten",
+		description_en: "Turn notifications on or off",
+		private:        false
+	}, "settings reply", |bot: &Bot, msg: &tg::Message, arg: Option<&str>| {
+		make_setnotify_text(msg, &bot.storage, arg)
+	});
+	router.register(CommandInfo {
+		name:           "about",
+		args:           "",
+		description:    "Informationen über den Bot",
+		description_en: "About the bot",
+		private:        false
+	}, "about text", |bot: &Bot, msg: &tg::Message, _: Option<&str>| {
+		make_about_text(msg, &bot.conf)
+	});
+	router.register(CommandInfo {
+		name:           "help",
+		args:           "",
+		description:    "Befehle auflisten",
+		description_en: "List the commands",
+		private:        false
+	}, "help text", |bot: &Bot, msg: &tg::Message, _: Option<&str>| {
+		make_help_text(msg, bot.router.commands())
+	});
+	router.register(CommandInfo {
+		name:           "start",
+		args:           "[<Tag>_<Mensa>]",
+		description:    "Begrüßung oder Speiseplan eines Deep Links",
+		description_en: "Welcome or the menu of a deep link",
+		private:        true
+	}, "start reply", make_start_text);
+	router
+}
+
+pub struct Bot {
+	conf:     Arc<conf::Config>,
+	api:      tg::Api,
+	cache:    Arc<MenuCache>,
+	storage:  Arc<Mutex<storage::Storage>>,
+	tomorrow: (u8, u8, u8),
+	patterns: Vec<regex::Regex>,
+	botname:  Option<String>,
+	router:   commands::Router,
+	replies:  RefCell<ReplyLog>
+}
+impl Bot {
+	fn chat_config(&self, chat: i64) -> ChatConfig {
+		ChatConfig::get(&self.conf, self.tomorrow, &self.storage, chat)
+	}
+
+	fn handle_update(&self, upd: tg::Update) {
+		match upd.message {
+			None    => {},
+			Some(m) => self.handle_message(m, false)
+		};
+		match upd.edited_message {
+			None    => {},
+			Some(m) => self.handle_message(m, true)
+		};
+		match upd.inline_query {
+			None    => {},
+			Some(q) => self.handle_inline_query(q)
+		};
+		match upd.callback_query {
+			None    => {},
+			Some(q) => self.handle_callback_query(q)
+		};
+	}
+
+	fn handle_callback_query(&self, query: tg::CallbackQuery) {
+		// stop the client's loading animation in any case
+		let answer = tg::CallbackAnswer::new(query.id.clone());
+		let _ = self.conf.general.retry("answer callback query", || self.api.answer_callback_query(&answer));
+
+		let msg = match query.message {
+			None        => return,
+			Some(ref m) => m
+		};
+		if !self.conf.is_allowed_in(Some(&query.from), &msg.chat) {
+			info!("callback query {} from {} in {} ignored", query.id, query.from, msg.chat);
+			return;
+		}
+		let data = match query.data {
+			None        => return,
+			Some(ref d) => d
+		};
+
+		let chatconf = self.chat_config(msg.chat.id);
+		match make_menu_edit(msg, data, &self.cache, &chatconf) {
+			None       => {},
+			Some(edit) => {
+				let _ = self.conf.general.retry("edit menu", || self.api.edit_text(&edit));
+			}
+		};
+	}
+
+	fn handle_inline_query(&self, query: tg::InlineQuery) {
+		if !self.conf.is_user_allowed(&query.from) {
+			info!("inline query {} from {} ignored", query.id, query.from);
+			return;
+		}
+		let chatconf = self.chat_config(query.from.id);
+		let answer = make_inline_answer(&query, &self.cache, &chatconf);
+		let _ = self.conf.general.retry("answer inline query", || self.api.answer_inline_query(&answer));
+	}
+
+	fn handle_message(&self, msg: tg::Message, edited: bool) {
+		if !self.conf.is_allowed(&msg) {
+			match msg.from {
+				None    => info!("message {} in {} ignored", msg.message_id, msg.chat),
+				Some(u) => info!("message {} from {} in {} ignored", msg.message_id, u, msg.chat)
+			};
+			return;
+		}
+
+		let mut cmds = commands::parse(&msg, self.botname.as_ref().map(String::as_str));
+		if cmds.is_empty() {
+			// try text patterns
+			let matched = match msg.text {
+				None        => false,
+				Some(ref t) => self.patterns.iter().any(|x| x.is_match(t))
+			};
+			if matched {
+				cmds.push(commands::Command {
+					name: String::from("mensa"),
+					arg:  None
+				});
+			}
+		}
+
+		let replies = self.router.dispatch(self, &msg, &cmds);
+		if replies.is_empty() && !edited {
+			info!("chat {} message {} ignored", msg.chat, msg.message_id);
+		} else {
+			// edits without commands remove the replies
+			self.deliver(&msg, edited, replies);
+		}
+	}
+
+	/// Send the replies to a message, if the message was edited the replies
+	/// sent before are edited instead.
+	fn deliver(&self, msg: &tg::Message, edited: bool, replies: Vec<(&str, tg::OutgoingText)>) {
+		let key = (msg.chat.id, msg.message_id);
+		let previous = if edited { self.replies.borrow().get(key) } else { Vec::new() };
+
+		let replies = replies.into_iter().flat_map(|(what, re)| re.split().into_iter().map(move |x| (what, x))).collect::<Vec<_>>();
+		let mut sent = Vec::new();
+		// replies the edited message no longer needs
+		for &(id, _) in previous.iter().skip(replies.len()) {
+			let _ = self.conf.general.retry("delete reply", || self.api.delete_message(msg.chat.id, id));
+		}
+		for (i, (what, re)) in replies.into_iter().enumerate() {
+			match previous.get(i) {
+				None => match self.conf.general.retry(&format!("send {}", what), || self.api.send_text(&re)) {
+					Err(_) => {},
+					Ok(m)  => sent.push((m.message_id, re.text))
+				},
+				Some(&(id, ref text)) => {
+					if *text != re.text {
+						let edit = re.to_edit(id);
+						let _ = self.conf.general.retry(&format!("edit {}", what), || self.api.edit_text(&edit));
+					}
+					sent.push((id, re.text));
+				}
+			}
+		}
+		self.replies.borrow_mut().insert(key, sent);
+	}
+}
+
+/// Remembers the latest replies to messages, so they can be edited when the
+/// message is.
+struct ReplyLog {
+	entries: VecDeque<((i64, i64), Vec<(i64, String)>)>
+}
+impl ReplyLog {
+	const SIZE: usize = 1000;
+
+	fn new() -> ReplyLog {
+		ReplyLog {
+			entries: VecDeque::with_capacity(ReplyLog::SIZE)
+		}
+	}
+
+	/// IDs and texts of the replies to message `key` (chat ID, message ID).
+	fn get(&self, key: (i64, i64)) -> Vec<(i64, String)> {
+		self.entries.iter().find(|x| x.0 == key).map_or_else(Vec::new, |x| x.1.clone())
+	}
+
+	fn insert(&mut self, key: (i64, i64), replies: Vec<(i64, String)>) {
+		self.entries.retain(|x| x.0 != key);
+		if self.entries.len() >= ReplyLog::SIZE {
+			self.entries.pop_front();
+		}
+		self.entries.push_back((key, replies));
+	}
+}
+
+const ALLOWED_UPDATES: &[&str] = &["message", "edited_message", "inline_query", "callback_query"];
+
+fn run_polling(bot: &mut Bot) {
+	let conf = bot.conf.clone();
+	// a webhook left behind by an unclean shutdown makes getUpdates fail
+	match conf.general.retry("delete webhook", || bot.api.delete_webhook()) {
+		Err(_) => std::process::exit(1),
+		Ok(_)  => {}
+	};
+	bot.api.set_offset(bot.storage.lock().unwrap().state.offset);
+	loop {
+		let upds = match conf.general.retry("get updates", || bot.api.get_updates(ALLOWED_UPDATES)) {
+			Err(_) => std::process::exit(1),
+			Ok(x)  => x
+		};
+
+		for upd in upds {
+			// an update is considered handled as soon as it is received, so a
+			// restart cannot process it twice
+			bot.api.set_latest_update(upd.update_id);
+			{
+				let mut storage = bot.storage.lock().unwrap();
+				storage.state.offset = bot.api.get_offset();
+				match storage.save() {
+					Err(e) => error!("cannot save state: {}", e),
+					Ok(_)  => {}
+				}
+			}
+			bot.handle_update(upd);
+		}
+	}
+}
+
+fn run_webhook(bot: &Bot) {
+	let conf = &bot.conf.webhook;
+	match bot.conf.general.retry("set webhook", || bot.api.set_webhook(&conf.url, &conf.secret, ALLOWED_UPDATES)) {
+		Err(_) => std::process::exit(1),
+		Ok(_)  => info!("webhook set to {}", conf.url)
+	};
+
+	// remove the webhook on shutdown, so the bot can be switched back to polling
+	let api   = bot.api.clone();
+	let gconf = bot.conf.clone();
+	match ctrlc::set_handler(move || {
+		let _ = gconf.general.retry("delete webhook", || api.delete_webhook());
+		std::process::exit(0);
+	}) {
+		Err(e) => warn!("cannot set up signal handler: {}", e),
+		Ok(_)  => {}
+	};
+
+	let result = webhook::serve(&conf.listen, &conf.secret, |upd| bot.handle_update(upd));
+	let _ = bot.conf.general.retry("delete webhook", || bot.api.delete_webhook());
+	match result {
+		Err(e) => {
+			error!("webhook server failed: {}", e);
+			std::process::exit(1);
+		},
+		Ok(_) => {}
+	}
+}
+
 fn main() {
 	let argv: Vec<String> = env::args().collect();
 	if argv.len() > 2 {
@@ -284,12 +1073,21 @@ fn main() {
 			eprintln!("{}: cannot load '{}': {}", argv[0], conf, e);
 			std::process::exit(1);
 		},
-		Ok(c) => c
+		Ok(c) => Arc::new(c)
 	};
 
 	let mut initerror = false;
 
-	let tomorrow = match parse_tomorrow(&conf.general.tomorrow) {
+	let storage = match storage::Storage::from_config(&conf.storage) {
+		Err(e) => {
+			eprintln!("{}: {}", argv[0], e);
+			initerror = true;
+			None
+		},
+		Ok(s) => Some(Arc::new(Mutex::new(s)))
+	};
+
+	let tomorrow = match date::parse_time(&conf.general.tomorrow) {
 		Err(e) => {
 			eprintln!("{}: {}", argv[0], e);
 			initerror = true;
@@ -298,6 +1096,36 @@ fn main() {
 		Ok(t) => t
 	};
 
+	if !(0.0 <= conf.general.threshold && conf.general.threshold <= 1.0) {
+		eprintln!("{}: threshold must be between 0 and 1", argv[0]);
+		initerror = true;
+	}
+
+	let cache = match source::from_config(&conf.source) {
+		Err(e) => {
+			eprintln!("{}: {}", argv[0], e);
+			initerror = true;
+			None
+		},
+		Ok(s) => {
+			// prefetched menus must not expire before they are refreshed, rounds
+			// start an interval after the previous one ended
+			let ttl = if conf.prefetch.interval > 0 { conf.general.cachettl + conf.prefetch.interval } else { conf.general.cachettl };
+			Some(Arc::new(cache::MenuCache::new(s, ttl)))
+		}
+	};
+
+	let mut prefetch_times = Vec::new();
+	for t in [&conf.prefetch.from, &conf.prefetch.until].iter() {
+		match date::parse_time(t) {
+			Err(e) => {
+				eprintln!("{}: {}", argv[0], e);
+				initerror = true;
+			},
+			Ok(x) => prefetch_times.push(x)
+		}
+	}
+
 	let mut patterns: Vec<regex::Regex> = Vec::new();
 	for pattern in conf.general.patterns.iter() {
 		match regex::Regex::new(&pattern) {
@@ -324,126 +1152,102 @@ fn main() {
 	if initerror {
 		std::process::exit(1);
 	}
+	let cache   = cache.unwrap();
+	let storage = storage.unwrap();
 
-	let mut api = tg::Api::new(&conf.general.token);
-
-	let botname = match conf.general.retry("retrieve bot name", || api.get_me()) {
-		Err(_) => None,
-		Ok(x)  => x.username
-	};
+	// aliases and groups name canteens exactly, so a typo would never match
+	let unlisted = conf.unlisted_shortcut_targets();
+	if !unlisted.is_empty() {
+		match cache.source().canteens() {
+			Err(e)       => warn!("cannot check aliases and groups: {}", e),
+			Ok(canteens) => for mensa in unlisted.iter().filter(|x| !canteens.iter().any(|c| c == *x)) {
+				warn!("alias or group names unknown canteen '{}'", mensa);
+			}
+		}
+	}
 
-	loop {
-		let upds = match conf.general.retry("get updates", || api.get_updates(&vec!["message"])) {
-			Err(_) => std::process::exit(1),
-			Ok(x)  => x
-		};
+	if conf.prefetch.interval > 0 {
+		prefetch::spawn(cache.clone(), conf.prefetch.interval, prefetch_times[0], prefetch_times[1]);
+	}
 
-		let mut latest_update: i64 = 0;
-		for upd in upds {
-			latest_update = upd.update_id;
+	// one rate limit for all senders
+	let api = tg::Api::new(&conf.general.token);
 
-			let msg = match upd.message {
-				None    => continue,
-				Some(m) => m
+	{
+		let api     = api.clone();
+		let conf    = conf.clone();
+		let cache   = cache.clone();
+		let storage = storage.clone();
+		subscriptions::spawn(storage.clone(), move |chat, sub, done| {
+			let chatconf = ChatConfig::get(&conf, tomorrow, &storage, chat);
+			let menu = match fetch_menu(&cache, Date::today(), sub.query.as_ref().map(String::as_str), &chatconf) {
+				Err(e) => {
+					error!("cannot fetch menu: {}", e);
+					return false;
+				},
+				Ok((menu, _)) => menu
 			};
-
-			if !conf.is_allowed(&msg) {
-				match msg.from {
-					None    => info!("message {} in {} ignored", msg.message_id, msg.chat),
-					Some(u) => info!("message {} from {} in {} ignored", msg.message_id, u, msg.chat)
-				};
-				continue;
+			if menu.is_empty() {
+				info!("no menu today for chat {}", chat);
+				return true;
 			}
-
-			let text = match msg.text {
-				None        => continue,
-				Some(ref t) => t
-			};
-
-			const CMD_MENSA: u32 = 0x01;
-			const CMD_ABOUT: u32 = 0x02;
-			let mut cmds: u32 = 0;
-			let mut arg_start: usize = 0;
-			let mut arg_end:   usize = 0;
-
-			for ent in msg.entities.iter().filter(|ref u| u.entity_type == "bot_command") {
-				match ent.extract(&text) {
-					Err(e)  => error!("cannot extract entity: {}", e),
-					Ok(cmd) => {
-						let cmd = match botname {
-							None              => &cmd,
-							Some(ref botname) => {
-								let n = cmd.len();
-								let m = botname.len();
-								if n > m && &cmd[(n - m - 1)..(n - m)] == "@" && &cmd[(n - m)..] == botname {
-									&cmd[..(n - m - 1)]
-								} else {
-									&cmd
-								}
-							}
-						};
-						if cmd == "/mensa" {
-							cmds |= CMD_MENSA;
-							arg_start = ent.offset + ent.length;
-							arg_end   = text.len();
-						} else if cmd == "/about" {
-							cmds |= CMD_ABOUT;
-						} else {
-							eprintln!("command: {}", cmd);
-						}
-					}
+			let txt = suggest_mensas(&menu, chatconf.threshold).unwrap_or_else(|| create_menu_message(&menu));
+			let mut re = tg::OutgoingText::new(chat, txt);
+			re.disable_notification = !chatconf.notify;
+			for part in re.split().iter().skip(*done) {
+				match conf.general.retry("send subscribed menu", || api.send_text(part)) {
+					// e.g. the bot was removed from the chat, try again tomorrow
+					Err(ref e) if !e.is_retriable() => return true,
+					Err(_) => return false,
+					Ok(_)  => *done += 1
 				}
 			}
+			true
+		});
+	}
 
-			if cmds & CMD_MENSA != 0 {
-				// narrow mensa search argument down
-				for ent in msg.entities.iter() {
-					if arg_start <= ent.offset && ent.offset < arg_end {
-						arg_end = ent.offset;
-					}
-				}
-			} else {
-				// try text patterns
-				for pattern in patterns.iter() {
-					if pattern.is_match(&text) {
-						cmds |= CMD_MENSA;
-						arg_end = 0;
-					}
+	{
+		let api  = api.clone();
+		let conf = conf.clone();
+		alerts::spawn(storage.clone(), cache.clone(), move |user, txt| {
+			let re = tg::OutgoingText::new(user, txt);
+			for part in re.split().iter() {
+				match conf.general.retry("send alert", || api.send_text(part)) {
+					// e.g. the user never started a private chat with the bot,
+					// do not try again every few minutes
+					Err(ref e) if !e.is_retriable() => return true,
+					Err(_) => return false,
+					Ok(_)  => {}
 				}
 			}
+			true
+		});
+	}
 
-			if cmds == 0 {
-				info!("chat {} message {} ignored", msg.chat, msg.message_id);
-			} else {
-				if cmds & CMD_MENSA != 0 {
-					let mut arg = None;
-					if arg_start < arg_end {
-						// extract mensa search argument
-						let argent = tg::MessageEntity{
-							entity_type: String::new(),
-							offset:      arg_start,
-							length:      arg_end - arg_start
-						};
-						match argent.extract(&text) {
-							Err(e) => error!("cannot extract argument: {}", e),
-							Ok(x)  => {
-								let x = x.trim();
-								if x.len() > 0 {
-									arg = Some(x.to_lowercase());
-								}
-							}
-						}
-					}
-					let re = make_menu_text(&msg, arg.as_ref().map(String::as_str), &conf.general.mensas, tomorrow.clone());
-					let _  = conf.general.retry("send menu", || api.send_text(&re));
-				}
-				if cmds & CMD_ABOUT != 0 {
-					let re = make_about_text(&msg, &conf);
-					let _  = conf.general.retry("send about text", || api.send_text(&re));
-				}
-			}
-		}
+	let mut bot = Bot {
+		conf:     conf.clone(),
+		api:      api,
+		cache:    cache,
+		storage:  storage,
+		tomorrow: tomorrow,
+		patterns: patterns,
+		botname:  None,
+		router:   create_router(),
+		replies:  RefCell::new(ReplyLog::new())
+	};
 
-		api.set_latest_update(latest_update);
+	bot.botname = match conf.general.retry("retrieve bot name", || bot.api.get_me()) {
+		Err(_) => None,
+		Ok(x)  => x.username
+	};
+
+	for (scope, lang, list) in bot.router.command_lists() {
+		let _ = conf.general.retry("set command list", || bot.api.set_my_commands(&list, scope, lang));
+	}
+
+	if conf.webhook.url.is_empty() {
+		run_polling(&mut bot);
+	} else {
+		run_webhook(&bot);
 	}
 }
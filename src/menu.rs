@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Clone, Default, Debug)]
+pub struct Prices {
+	// all prices in cents
+	pub student: Option<u32>,
+	pub staff:   Option<u32>,
+	pub guest:   Option<u32>
+}
+impl Prices {
+	pub fn is_empty(&self) -> bool {
+		self.student.is_none() && self.staff.is_none() && self.guest.is_none()
+	}
+
+	pub fn parse_price(s: &str) -> Option<u32> {
+		let s = s.trim().trim_right_matches("€").trim_right_matches("EUR").trim();
+		let mut parts = s.splitn(2, |c| c == ',' || c == '.');
+		let euros = match parts.next().map(|x| x.trim().parse::<u32>()) {
+			Some(Ok(x)) => x,
+			_           => return None
+		};
+		let cents = match parts.next() {
+			None    => 0,
+			Some(x) => {
+				let x = x.trim();
+				match x.parse::<u32>() {
+					Err(_) => return None,
+					Ok(c)  => if x.len() == 1 { c * 10 } else if x.len() == 2 { c } else { return None; }
+				}
+			}
+		};
+		Some(euros * 100 + cents)
+	}
+
+	pub fn format_price(cents: u32) -> String {
+		format!("{},{:02} €", cents / 100, cents % 100)
+	}
+}
+impl fmt::Display for Prices {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut sep = "";
+		for price in [self.student, self.staff, self.guest].iter() {
+			match *price {
+				None    => {},
+				Some(p) => {
+					write!(f, "{}{}", sep, Prices::format_price(p))?;
+					sep = " / ";
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Diet {
+	pub vegan:      bool,
+	pub vegetarian: bool,
+	pub pork:       bool,
+	pub beef:       bool,
+	pub alcohol:    bool,
+	pub garlic:     bool
+}
+impl Diet {
+	/// Set the flags a free-form description (e.g. an icon's title or a note)
+	/// refers to, returns whether any flag was recognized.
+	pub fn add_description(&mut self, desc: &str) -> bool {
+		let desc = desc.to_lowercase();
+		let mut found = false;
+		if desc.contains("vegan") {
+			self.vegan = true;
+			found = true;
+		}
+		if desc.contains("vegetari") {
+			self.vegetarian = true;
+			found = true;
+		}
+		if desc.contains("schwein") || desc.contains("pork") {
+			self.pork = true;
+			found = true;
+		}
+		if desc.contains("rind") || desc.contains("beef") {
+			self.beef = true;
+			found = true;
+		}
+		if desc.contains("alkohol") || desc.contains("alcohol") {
+			self.alcohol = true;
+			found = true;
+		}
+		if desc.contains("knoblauch") || desc.contains("garlic") {
+			self.garlic = true;
+			found = true;
+		}
+		found
+	}
+}
+impl fmt::Display for Diet {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let flags = [
+			(self.vegan,      "vegan"),
+			(self.vegetarian && !self.vegan, "vegetarisch"),
+			(self.pork,       "Schwein"),
+			(self.beef,       "Rind"),
+			(self.alcohol,    "Alkohol"),
+			(self.garlic,     "Knoblauch")
+		];
+		let mut sep = "";
+		for &(set, name) in flags.iter() {
+			if set {
+				write!(f, "{}{}", sep, name)?;
+				sep = ", ";
+			}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Meal {
+	pub name:     String,
+	pub prices:   Prices,
+	pub diet:     Diet,
+	pub url:      Option<String>,
+	pub sold_out: bool
+}
+impl Meal {
+	pub fn new<S: Into<String>>(name: S) -> Meal {
+		Meal {
+			name:     name.into(),
+			prices:   Prices::default(),
+			diet:     Diet::default(),
+			url:      None,
+			sold_out: false
+		}
+	}
+}
+impl fmt::Display for Meal {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.name)?;
+		let diet = self.diet.to_string();
+		if !diet.is_empty() {
+			write!(f, " [{}]", diet)?;
+		}
+		if self.sold_out {
+			f.write_str(" (ausverkauft)")?;
+		} else if !self.prices.is_empty() {
+			write!(f, " ({})", self.prices)?;
+		}
+		match self.url {
+			None        => {},
+			Some(ref x) => write!(f, "\n   {}", x)?
+		}
+		Ok(())
+	}
+}
+
+/// Meals of a single day by canteen name.
+pub type Menu = BTreeMap<String, Vec<Meal>>;
+
+/// Resolve a possibly relative link `href` found on the page at `base`.
+pub fn resolve_url(base: &str, href: &str) -> String {
+	if href.starts_with("http://") || href.starts_with("https://") {
+		String::from(href)
+	} else if href.starts_with("//") {
+		let scheme = base.find("//").map_or("https:", |i| &base[..i]);
+		format!("{}{}", scheme, href)
+	} else if href.starts_with("/") {
+		// keep scheme and host of base
+		let host_end = base.find("//")
+				.and_then(|i| base[(i + 2)..].find("/").map(|j| i + 2 + j))
+				.unwrap_or(base.len());
+		format!("{}{}", &base[..host_end], href)
+	} else {
+		// strip file name of base
+		let dir_end = base.rfind("/").map_or(base.len(), |i| i + 1);
+		format!("{}{}", &base[..dir_end], href)
+	}
+}
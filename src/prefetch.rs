@@ -0,0 +1,27 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use cache::MenuCache;
+use date;
+use date::Date;
+
+/// Refresh the menus of today and tomorrow every `interval` seconds while the
+/// local time is between `from` and `until`.
+pub fn spawn(cache: Arc<MenuCache>, interval: u64, from: (u8, u8, u8), until: (u8, u8, u8)) -> thread::JoinHandle<()> {
+	thread::spawn(move || {
+		loop {
+			let now = date::now_time();
+			if date::time_cmp(now, from) >= 0 && date::time_cmp(now, until) < 0 {
+				let today = Date::today();
+				for day in [today, today.add_days(1)].iter() {
+					match cache.refresh(*day) {
+						Err(e) => warn!("cannot prefetch menu for {}: {}", day, e),
+						Ok(_)  => debug!("prefetched menu for {}", day)
+					}
+				}
+			}
+			thread::sleep(Duration::from_secs(interval));
+		}
+	})
+}
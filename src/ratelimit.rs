@@ -0,0 +1,88 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// At most `count` messages per `secs` seconds.
+struct Limit {
+	count: usize,
+	secs:  u64
+}
+
+/// Limits recommended by Telegram: 30 messages per second overall, one
+/// message per second in a chat and 20 messages per minute in a group.
+const GLOBAL: Limit = Limit { count: 30, secs: 1 };
+const CHAT:   Limit = Limit { count: 1,  secs: 1 };
+const GROUP:  Limit = Limit { count: 20, secs: 60 };
+
+struct Sent {
+	global: VecDeque<Instant>,
+	chats:  HashMap<i64, VecDeque<Instant>>
+}
+
+/// Throttles outgoing messages, shared by everything sending to Telegram.
+pub struct RateLimiter {
+	sent: Mutex<Sent>
+}
+impl RateLimiter {
+	pub fn new() -> RateLimiter {
+		RateLimiter {
+			sent: Mutex::new(Sent {
+				global: VecDeque::new(),
+				chats:  HashMap::new()
+			})
+		}
+	}
+
+	/// Time until another message is allowed by `limit` given the times of the
+	/// latest messages.
+	fn delay(times: &VecDeque<Instant>, limit: &Limit, now: Instant) -> Duration {
+		if times.len() < limit.count {
+			return Duration::from_secs(0);
+		}
+		let until = times[times.len() - limit.count] + Duration::from_secs(limit.secs);
+		if until > now { until - now } else { Duration::from_secs(0) }
+	}
+
+	/// Block until a message may be sent to `chat` and count it as sent.
+	pub fn wait(&self, chat: i64) {
+		loop {
+			let delay = {
+				let mut sent = self.sent.lock().unwrap();
+				let now = Instant::now();
+
+				// forget messages no limit cares about anymore
+				let minute = Duration::from_secs(GROUP.secs);
+				while sent.global.front().map_or(false, |x| now - *x >= Duration::from_secs(GLOBAL.secs)) {
+					sent.global.pop_front();
+				}
+				for times in sent.chats.values_mut() {
+					while times.front().map_or(false, |x| now - *x >= minute) {
+						times.pop_front();
+					}
+				}
+				sent.chats.retain(|_, times| !times.is_empty());
+
+				let mut delay = RateLimiter::delay(&sent.global, &GLOBAL, now);
+				match sent.chats.get(&chat) {
+					None        => {},
+					Some(times) => {
+						delay = delay.max(RateLimiter::delay(times, &CHAT, now));
+						if chat < 0 {
+							delay = delay.max(RateLimiter::delay(times, &GROUP, now));
+						}
+					}
+				}
+
+				if delay == Duration::from_secs(0) {
+					sent.global.push_back(now);
+					sent.chats.entry(chat).or_insert_with(VecDeque::new).push_back(now);
+					return;
+				}
+				delay
+			};
+			debug!("delaying message to {} by {:?}", chat, delay);
+			thread::sleep(delay);
+		}
+	}
+}
@@ -0,0 +1,30 @@
+use conf;
+use date::Date;
+use menu::Menu;
+
+mod openmensa;
+mod studentenwerk;
+
+pub use self::openmensa::OpenMensa;
+pub use self::studentenwerk::Studentenwerk;
+
+/// Somewhere to get canteen menus from.
+pub trait MenuSource: Send + Sync {
+	/// Names of all canteens known to the source.
+	fn canteens(&self) -> Result<Vec<String>, String>;
+
+	/// Menus of all canteens open on `day`.
+	fn menu(&self, day: Date) -> Result<Menu, String>;
+
+	/// Web page where humans can look up the menu of `day`.
+	fn url(&self, day: Date) -> String;
+}
+
+pub fn from_config(conf: &conf::ConfigSource) -> Result<Box<dyn MenuSource>, String> {
+	let url = conf.url.as_ref().map(String::as_str);
+	match conf.source_type.as_str() {
+		"studentenwerk" => Ok(Box::new(Studentenwerk::new(url))),
+		"openmensa"     => Ok(Box::new(OpenMensa::new(url, conf.city.as_ref().map(String::as_str), &conf.canteens)?)),
+		x               => Err(format!("unknown menu source '{}'", x))
+	}
+}
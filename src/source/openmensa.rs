@@ -0,0 +1,150 @@
+use std::sync::Mutex;
+
+extern crate reqwest;
+extern crate serde;
+
+use date::Date;
+use menu::{Meal, Menu};
+use source::MenuSource;
+
+pub const DEFAULT_URL: &str = "https://openmensa.org/api/v2/";
+
+#[derive(Deserialize, Clone)]
+struct Canteen {
+	id:   u64,
+	name: String,
+	city: Option<String>
+}
+
+#[derive(Deserialize, Default)]
+struct Prices {
+	students:  Option<f64>,
+	employees: Option<f64>,
+	others:    Option<f64>
+}
+
+#[derive(Deserialize)]
+struct ApiMeal {
+	name:   String,
+	#[serde(default)]
+	prices: Prices,
+	#[serde(default)]
+	notes:  Vec<String>
+}
+impl ApiMeal {
+	fn cents(x: Option<f64>) -> Option<u32> {
+		x.and_then(|x| if x > 0.0 { Some((x * 100.0).round() as u32) } else { None })
+	}
+
+	fn into_meal(self) -> Meal {
+		let mut meal = Meal::new(self.name);
+		meal.prices.student = ApiMeal::cents(self.prices.students);
+		meal.prices.staff   = ApiMeal::cents(self.prices.employees);
+		meal.prices.guest   = ApiMeal::cents(self.prices.others);
+		for note in self.notes.iter() {
+			if note.to_lowercase().contains("ausverkauft") {
+				meal.sold_out = true;
+			} else {
+				meal.diet.add_description(note);
+			}
+		}
+		meal
+	}
+}
+
+/// Queries the OpenMensa API, see https://doc.openmensa.org/api/v2/
+pub struct OpenMensa {
+	baseurl:  String,
+	city:     Option<String>,
+	ids:      Vec<u64>,
+	canteens: Mutex<Option<Vec<Canteen>>>
+}
+impl OpenMensa {
+	pub fn new(baseurl: Option<&str>, city: Option<&str>, ids: &Vec<u64>) -> Result<OpenMensa, String> {
+		if city.is_none() && ids.is_empty() {
+			return Err(String::from("OpenMensa needs a city or canteen IDs"));
+		}
+		let mut baseurl = String::from(baseurl.unwrap_or(DEFAULT_URL));
+		if !baseurl.ends_with("/") {
+			baseurl.push_str("/");
+		}
+		Ok(OpenMensa {
+			baseurl:  baseurl,
+			city:     city.map(|x| x.to_lowercase()),
+			ids:      ids.clone(),
+			canteens: Mutex::new(None)
+		})
+	}
+
+	/// Returns `None` if the resource does not exist.
+	fn get<T>(&self, path: &str) -> Result<Option<T>, String> where for<'de> T: serde::Deserialize<'de> {
+		let url = format!("{}{}", self.baseurl, path);
+		debug!("fetching {}", url);
+		let mut resp = match reqwest::get(&url) {
+			Err(e) => return Err(format!("{}", e)),
+			Ok(r)  => r
+		};
+		if resp.status() == reqwest::StatusCode::NotFound {
+			return Ok(None);
+		} else if resp.status() != reqwest::StatusCode::Ok {
+			return Err(format!("HTTP error {}", resp.status()));
+		}
+		resp.json().map(Some).map_err(|e| format!("deserialization error: {}", e))
+	}
+
+	fn fetch_canteens(&self) -> Result<Vec<Canteen>, String> {
+		if !self.ids.is_empty() {
+			let ids = self.ids.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",");
+			return self.get(&format!("canteens?ids={}", ids)).map(|x| x.unwrap_or_else(Vec::new));
+		}
+
+		// the canteen list is paginated
+		let city = self.city.as_ref().map(String::as_str).unwrap_or("");
+		let mut canteens = Vec::new();
+		let mut page = 1;
+		loop {
+			let chunk: Vec<Canteen> = match self.get(&format!("canteens?limit=100&page={}", page))? {
+				None    => break,
+				Some(x) => x
+			};
+			if chunk.is_empty() {
+				break;
+			}
+			canteens.extend(chunk.into_iter().filter(|c| c.city.as_ref().map_or(false, |x| x.to_lowercase() == city)));
+			page += 1;
+		}
+		Ok(canteens)
+	}
+
+	fn get_canteens(&self) -> Result<Vec<Canteen>, String> {
+		let mut cached = self.canteens.lock().unwrap();
+		if cached.is_none() {
+			*cached = Some(self.fetch_canteens()?);
+		}
+		Ok(cached.as_ref().unwrap().clone())
+	}
+}
+impl MenuSource for OpenMensa {
+	fn canteens(&self) -> Result<Vec<String>, String> {
+		self.get_canteens().map(|x| x.into_iter().map(|c| c.name).collect())
+	}
+
+	fn menu(&self, day: Date) -> Result<Menu, String> {
+		let mut menu = Menu::new();
+		for canteen in self.get_canteens()? {
+			let meals: Vec<ApiMeal> = match self.get(&format!("canteens/{}/days/{}/meals", canteen.id, day))? {
+				None    => continue, // closed
+				Some(x) => x
+			};
+			if meals.is_empty() {
+				continue;
+			}
+			menu.insert(canteen.name, meals.into_iter().map(ApiMeal::into_meal).collect());
+		}
+		Ok(menu)
+	}
+
+	fn url(&self, _day: Date) -> String {
+		String::from("https://openmensa.org/")
+	}
+}
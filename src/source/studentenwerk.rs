@@ -0,0 +1,220 @@
+extern crate reqwest;
+extern crate select;
+
+use std::collections::BTreeSet;
+
+use self::select::predicate::Predicate;
+
+use date::Date;
+use menu;
+use menu::{Meal, Menu};
+use source::MenuSource;
+
+pub const DEFAULT_URL: &str = "https://www.studentenwerk-dresden.de/mensen/speiseplan/";
+
+fn strip_white(s: &str) -> String {
+	let words = s.split_whitespace();
+	let mut s = String::with_capacity(s.len());
+	for w in words.into_iter() {
+		s.push_str(w);
+		s.push_str(" ");
+	}
+	let n = s.len();
+	if n > 0 {
+		s.truncate(n - 1);
+		s.shrink_to_fit();
+	}
+	s
+}
+
+fn get_text_content(x: &select::node::Node) -> String {
+	strip_white(&x.children().filter_map(|x| x.as_text()).collect::<String>())
+}
+
+fn parse_meal(tr: &select::node::Node, url: &str) -> Option<Meal> {
+	let td = match tr.find(select::predicate::Name("td").and(select::predicate::Class("text"))).next() {
+		None    => return None,
+		Some(x) => x
+	};
+
+	// sold out meals are not always linked
+	let mut meal = match td.find(select::predicate::Name("a")).next() {
+		None    => Meal::new(get_text_content(&td)),
+		Some(a) => {
+			let mut meal = Meal::new(get_text_content(&a));
+			meal.url = a.attr("href").map(|href| menu::resolve_url(url, href));
+			meal
+		}
+	};
+	if meal.name.is_empty() {
+		return None;
+	}
+
+	// diet icons
+	for img in tr.find(select::predicate::Name("img")) {
+		for attr in ["title", "alt", "src"].iter() {
+			match img.attr(*attr) {
+				None    => {},
+				Some(x) => if meal.diet.add_description(x) { break; }
+			}
+		}
+	}
+
+	let classes = tr.attr("class").unwrap_or("").to_lowercase();
+	meal.sold_out = classes.contains("ausverkauft");
+
+	match tr.find(select::predicate::Name("td").and(select::predicate::Class("preise"))).next() {
+		None     => {},
+		Some(td) => {
+			let txt = strip_white(&td.text());
+			if txt.to_lowercase().contains("ausverkauft") {
+				meal.sold_out = true;
+			} else {
+				let mut prices = txt.split("/").map(menu::Prices::parse_price);
+				meal.prices.student = prices.next().and_then(|x| x);
+				meal.prices.staff   = prices.next().and_then(|x| x);
+				meal.prices.guest   = prices.next().and_then(|x| x);
+			}
+		}
+	}
+
+	Some(meal)
+}
+
+fn parse_menu(doc: select::document::Document, url: &str) -> Menu {
+	let mut menu = Menu::new();
+	for table in doc.find(select::predicate::Name("table").and(select::predicate::Class("speiseplan"))) {
+		let mut children = table.children();
+
+		// find thead
+		let mut theadopt: Option<select::node::Node>;
+		loop {
+			theadopt = children.next();
+			let thead = match theadopt {
+				None    => break,
+				Some(x) => x
+			};
+			match thead.name() {
+				None    => continue,
+				Some(x) => if x != "thead" {
+					continue
+				}
+			};
+			break;
+		}
+		let thead = match theadopt {
+			None    => continue,
+			Some(x) => x
+		};
+
+		// get mensa name
+		let th = match thead.find(select::predicate::Name("th")).next() {
+			None    => continue,
+			Some(x) => x
+		};
+		let mut mensa = get_text_content(&th);
+		if mensa.starts_with("Angebote ") {
+			mensa = String::from(&mensa[9..]);
+		}
+
+		// get meals
+		let tbodies = children
+				.filter(|&x| x.name().map_or(false, |y| y == "tbody"))
+				.take(4)
+				.enumerate()
+				.filter_map(|(i, x)| if i == 0 || i == 3 { Some(x) } else { None });
+		let trs = tbodies
+				.flat_map(|x| x.children())
+				.filter(|&x| x.name().map_or(false, |y| y == "tr"));
+		let meals = trs.filter_map(|tr| parse_meal(&tr, url)).collect::<Vec<_>>();
+
+		if meals.is_empty() {
+			continue;
+		}
+
+		menu.insert(mensa, meals);
+	}
+
+	menu
+}
+
+/// Scrapes the menu pages of the Studentenwerk Dresden.
+pub struct Studentenwerk {
+	baseurl: String
+}
+impl Studentenwerk {
+	pub fn new(baseurl: Option<&str>) -> Studentenwerk {
+		let mut baseurl = String::from(baseurl.unwrap_or(DEFAULT_URL));
+		if !baseurl.ends_with("/") {
+			baseurl.push_str("/");
+		}
+		Studentenwerk {
+			baseurl: baseurl
+		}
+	}
+
+	fn page_url(&self, day: Date) -> Option<String> {
+		let today = Date::today();
+		let week  = day.week_start().days_since(&today.week_start()) / 7;
+		match day.days_since(&today) {
+			0 => Some(self.baseurl.clone()),
+			1 => Some(format!("{}morgen.html", self.baseurl)),
+			_ => if week < 0 {
+				None
+			} else {
+				Some(format!("{}w{}-d{}.html", self.baseurl, week, day.weekday() + 1))
+			}
+		}
+	}
+}
+impl MenuSource for Studentenwerk {
+	/// Canteens on the menus of this week's workdays, or the next week's on
+	/// weekends, so canteens closed today are known as well.
+	fn canteens(&self) -> Result<Vec<String>, String> {
+		let mut day = Date::today();
+		if day.weekday() >= 5 {
+			day = day.add_days(7);
+		}
+		let monday = day.week_start();
+
+		let mut canteens = BTreeSet::new();
+		let mut error = None;
+		for i in 0..5 {
+			match self.menu(monday.add_days(i)) {
+				Err(e)   => error = Some(e),
+				Ok(menu) => canteens.extend(menu.into_iter().map(|x| x.0))
+			}
+		}
+		match error {
+			Some(e) if canteens.is_empty() => Err(e),
+			_                              => Ok(canteens.into_iter().collect())
+		}
+	}
+
+	fn menu(&self, day: Date) -> Result<Menu, String> {
+		let url = match self.page_url(day) {
+			None    => return Ok(Menu::new()), // past weeks are not available
+			Some(x) => x
+		};
+		debug!("fetching {}", url);
+
+		let resp = match reqwest::get(&url) {
+			Err(e) => return Err(format!("{}", e)),
+			Ok(r)  => r
+		};
+		if resp.status() != reqwest::StatusCode::Ok {
+			return Err(format!("HTTP error {}", resp.status()));
+		}
+
+		let doc = match select::document::Document::from_read(resp) {
+			Err(e) => return Err(format!("{}", e)),
+			Ok(d)  => d
+		};
+
+		Ok(parse_menu(doc, &url))
+	}
+
+	fn url(&self, day: Date) -> String {
+		self.page_url(day).unwrap_or_else(|| self.baseurl.clone())
+	}
+}
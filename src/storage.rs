@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+extern crate serde_json;
+
+use conf;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Subscription {
+	/// `HH:MM` at which the menu is sent
+	pub time:      String,
+	/// canteen search argument, `None` for the default canteens
+	pub query:     Option<String>,
+	/// day the menu was last sent
+	#[serde(default)]
+	pub last_sent: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Alerts {
+	pub keywords:     Vec<String>,
+	/// day `announced` refers to
+	#[serde(default)]
+	pub announced_on: Option<String>,
+	/// names of meals already announced
+	#[serde(default)]
+	pub announced:    BTreeSet<String>
+}
+
+/// Overrides of the configuration file for a single chat.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ChatSettings {
+	#[serde(default)]
+	pub mensas:   Option<Vec<String>>,
+	#[serde(default)]
+	pub tomorrow: Option<String>,
+	#[serde(default)]
+	pub notify:   Option<bool>
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+	/// next update to request
+	#[serde(default)]
+	pub offset:        i64,
+	/// by chat ID
+	#[serde(default)]
+	pub chats:         BTreeMap<i64, ChatSettings>,
+	/// by chat ID
+	#[serde(default)]
+	pub subscriptions: BTreeMap<i64, Subscription>,
+	/// by user ID
+	#[serde(default)]
+	pub alerts:        BTreeMap<i64, Alerts>
+}
+
+/// Somewhere to persist the bot's state.
+pub trait Backend: Send {
+	fn load(&self) -> Result<State, String>;
+	fn save(&self, state: &State) -> Result<(), String>;
+}
+
+/// Keeps the state in a JSON file.
+pub struct JsonFile {
+	path: PathBuf
+}
+impl JsonFile {
+	pub fn new<P: AsRef<Path>>(path: P) -> JsonFile {
+		JsonFile {
+			path: path.as_ref().to_path_buf()
+		}
+	}
+}
+impl Backend for JsonFile {
+	fn load(&self) -> Result<State, String> {
+		let path = &self.path;
+		match File::open(path) {
+			Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(State::default()),
+			Err(e)    => Err(format!("cannot open `{}`: {}", path.display(), e)),
+			Ok(mut f) => {
+				let mut s = String::new();
+				f.read_to_string(&mut s).map_err(|e| format!("cannot read `{}`: {}", path.display(), e))?;
+				serde_json::from_str(&s).map_err(|e| format!("cannot load `{}`: {}", path.display(), e))
+			}
+		}
+	}
+
+	/// Write the state to a temporary file and move it over the old one, so
+	/// the file is never left half-written.
+	fn save(&self, state: &State) -> Result<(), String> {
+		let mut tmp = self.path.clone().into_os_string();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+		let data = serde_json::to_string_pretty(state).map_err(|e| format!("cannot serialize state: {}", e))?;
+		{
+			let mut f = File::create(&tmp).map_err(|e| format!("cannot create `{}`: {}", tmp.display(), e))?;
+			f.write_all(data.as_bytes()).map_err(|e| format!("cannot write `{}`: {}", tmp.display(), e))?;
+			f.sync_all().map_err(|e| format!("cannot write `{}`: {}", tmp.display(), e))?;
+		}
+		fs::rename(&tmp, &self.path).map_err(|e| format!("cannot replace `{}`: {}", self.path.display(), e))
+	}
+}
+
+/// Forgets the state on exit.
+pub struct Memory;
+impl Backend for Memory {
+	fn load(&self) -> Result<State, String> {
+		Ok(State::default())
+	}
+
+	fn save(&self, _state: &State) -> Result<(), String> {
+		Ok(())
+	}
+}
+
+pub struct Storage {
+	backend:   Box<dyn Backend>,
+	pub state: State
+}
+impl Storage {
+	pub fn open(backend: Box<dyn Backend>) -> Result<Storage, String> {
+		let state = backend.load()?;
+		Ok(Storage {
+			backend: backend,
+			state:   state
+		})
+	}
+
+	pub fn from_config(conf: &conf::ConfigStorage) -> Result<Storage, String> {
+		match conf.storage_type.as_str() {
+			"json"   => Storage::open(Box::new(JsonFile::new(&conf.path))),
+			"memory" => Storage::open(Box::new(Memory)),
+			x        => Err(format!("unknown storage type '{}'", x))
+		}
+	}
+
+	pub fn save(&self) -> Result<(), String> {
+		self.backend.save(&self.state)
+	}
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use date;
+use date::Date;
+use storage::{Storage, Subscription};
+
+pub const DEFAULT_TIME: &str = "11:00";
+
+/// Parse the arguments of `/subscribe [HH:MM] [<canteens>]`.
+pub fn parse_args(arg: Option<&str>) -> Result<Subscription, String> {
+	let mut time  = String::from(DEFAULT_TIME);
+	let mut query = Vec::new();
+	for (i, word) in arg.unwrap_or("").split_whitespace().enumerate() {
+		if i == 0 && word.contains(":") {
+			let t = date::parse_time(word)?;
+			if t.0 >= 24 {
+				return Err(format!("invalid timestamp '{}'", word));
+			}
+			time = format!("{:02}:{:02}", t.0, t.1);
+		} else {
+			query.push(word);
+		}
+	}
+	Ok(Subscription {
+		time:      time,
+		query:     if query.is_empty() { None } else { Some(query.join(" ")) },
+		last_sent: None
+	})
+}
+
+/// Subscriptions whose time has come today but which were not sent yet, those
+/// more than an hour late are skipped.
+fn get_due(storage: &Storage, today: &str) -> Vec<(i64, Subscription)> {
+	let now = date::now_time();
+	storage.state.subscriptions.iter()
+			.filter(|&(_, sub)| sub.last_sent.as_ref().map_or(true, |x| x != today))
+			.filter(|&(_, sub)| match date::parse_time(&sub.time) {
+				Err(_) => false,
+				Ok(t)  => date::time_cmp(now, t) >= 0 && date::time_cmp(now, (t.0 + 1, t.1, t.2)) < 0
+			})
+			.map(|(chat, sub)| (*chat, sub.clone()))
+			.collect()
+}
+
+/// Send the menu to subscribed chats on weekdays, `send` is called with the
+/// chat ID, its subscription and the number of messages already delivered
+/// today, which it updates, and returns whether the chat is done for today.
+pub fn spawn<F>(storage: Arc<Mutex<Storage>>, send: F) -> thread::JoinHandle<()>
+		where F: Fn(i64, &Subscription, &mut usize) -> bool + Send + 'static {
+	thread::spawn(move || {
+		// parts of split menus sent so far, so they are not repeated on retries
+		let mut progress: HashMap<i64, usize> = HashMap::new();
+		let mut progress_day = String::new();
+		loop {
+			let today = Date::today();
+			if today.weekday() < 5 {
+				let today = today.to_string();
+				if progress_day != today {
+					progress.clear();
+					progress_day = today.clone();
+				}
+				let due = get_due(&storage.lock().unwrap(), &today);
+				let mut sent = Vec::new();
+				for (chat, sub) in due {
+					info!("sending subscribed menu to {}", chat);
+					if send(chat, &sub, progress.entry(chat).or_insert(0)) {
+						sent.push(chat);
+					}
+				}
+
+				if !sent.is_empty() {
+					let mut storage = storage.lock().unwrap();
+					for chat in sent {
+						progress.remove(&chat);
+						// might have unsubscribed in the meantime
+						match storage.state.subscriptions.get_mut(&chat) {
+							None      => {},
+							Some(sub) => sub.last_sent = Some(today.clone())
+						}
+					}
+					match storage.save() {
+						Err(e) => error!("cannot save state: {}", e),
+						Ok(_)  => {}
+					}
+				}
+			}
+			thread::sleep(Duration::from_secs(30));
+		}
+	})
+}
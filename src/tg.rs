@@ -1,38 +1,94 @@
 use std;
 use std::error;
 use std::fmt;
+use std::io::Read;
 use std::string::FromUtf16Error;
+use std::sync::Arc;
 
 extern crate reqwest;
 extern crate serde;
 extern crate serde_json;
 
+use ratelimit::RateLimiter;
+
 #[derive(Debug)]
-pub struct Error {
-	desc: String
+pub enum Error {
+	/// the request could not be sent or the response not received
+	Network(reqwest::Error),
+	/// unsuccessful HTTP status without an explanation
+	Http(reqwest::StatusCode),
+	/// error reported by the Bot API
+	Api {
+		code:        i64,
+		description: String,
+		parameters:  Option<ResponseParameters>
+	},
+	/// the response is not what was expected
+	Deserialization(String)
 }
 impl Error {
-	fn new<S: Into<String>>(desc: S) -> Error {
-		Error { desc: desc.into() }
+	/// Whether repeating the request might succeed, bad requests or chats that
+	/// blocked the bot will fail again.
+	pub fn is_retriable(&self) -> bool {
+		match *self {
+			Error::Network(_)         => true,
+			Error::Http(status)       => status.as_u16() == 429 || status.is_server_error(),
+			Error::Api { code, .. }   => code == 429 || code >= 500,
+			Error::Deserialization(_) => false
+		}
+	}
+
+	fn parameters(&self) -> Option<&ResponseParameters> {
+		match *self {
+			Error::Api { ref parameters, .. } => parameters.as_ref(),
+			_                                 => None
+		}
+	}
+
+	/// Seconds to wait before repeating a request exceeding flood control.
+	pub fn retry_after(&self) -> Option<u64> {
+		self.parameters().and_then(|x| x.retry_after)
+	}
+
+	/// New ID of a group that was migrated to a supergroup.
+	pub fn migrate_to_chat_id(&self) -> Option<i64> {
+		self.parameters().and_then(|x| x.migrate_to_chat_id)
 	}
 }
 impl error::Error for Error {
 	fn description(&self) -> &str {
-		&self.desc
+		match *self {
+			Error::Network(_)                  => "network error",
+			Error::Http(_)                     => "HTTP error",
+			Error::Api { ref description, .. } => description,
+			Error::Deserialization(ref desc)   => desc
+		}
 	}
 }
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}", self.desc)
+		match *self {
+			Error::Network(ref e)                    => write!(f, "reqwest error: {}", e),
+			Error::Http(status)                      => write!(f, "Telegram Bot API HTTP error: {}", status),
+			Error::Api { code, ref description, .. } => write!(f, "Telegram Bot API Error: {} {}", code, description),
+			Error::Deserialization(ref desc)         => write!(f, "deserialization error: {}", desc)
+		}
 	}
 }
 
+#[derive(Deserialize, Debug)]
+pub struct ResponseParameters {
+	pub migrate_to_chat_id: Option<i64>,
+	pub retry_after:        Option<u64>
+}
+
 #[derive(Deserialize)]
 struct Response {
 	ok:          bool,
 	error_code:  Option<i64>,
 	description: Option<String>,
-	result:      Option<serde_json::Value>
+	result:      Option<serde_json::Value>,
+	parameters:  Option<ResponseParameters>
 }
 
 #[derive(Deserialize, Debug)]
@@ -91,9 +147,12 @@ pub struct MessageEntity {
 	pub length:      usize
 }
 impl MessageEntity {
-	// TODO extract multiple at once with iterators
-	pub fn extract(&self, text: &str) -> Result<String, FromUtf16Error> {
-		String::from_utf16(&text.encode_utf16().skip(self.offset).take(self.length).collect::<Vec<u16>>())
+	/// Extract the entity from the message text encoded as UTF-16, so several
+	/// entities can be extracted without encoding the text again.
+	pub fn extract(&self, text: &[u16]) -> Result<String, FromUtf16Error> {
+		let start = self.offset.min(text.len());
+		let end   = (self.offset + self.length).min(text.len());
+		String::from_utf16(&text[start..end])
 	}
 }
 
@@ -112,24 +171,250 @@ impl Message {
 			chat_id:    self.chat.id,
 			text:       text.into(),
 			parse_mode: None,
+			disable_web_page_preview: true,
 			disable_notification: false,
-			reply_to_message_id:  Some(self.message_id)
+			reply_to_message_id:  Some(self.message_id),
+			reply_markup:         None
+		}
+	}
+
+	pub fn edit_text<S: Into<String>>(&self, text: S) -> EditText {
+		EditText {
+			chat_id:      self.chat.id,
+			message_id:   self.message_id,
+			text:         text.into(),
+			parse_mode:   None,
+			disable_web_page_preview: true,
+			reply_markup: None
 		}
 	}
 }
 
+#[derive(Deserialize, Debug)]
+pub struct InlineQuery {
+	pub id:    String,
+	pub from:  User,
+	pub query: String
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CallbackQuery {
+	pub id:      String,
+	pub from:    User,
+	pub message: Option<Message>,
+	pub data:    Option<String>
+}
+
 #[derive(Deserialize)]
 pub struct Update {
-	pub update_id: i64,
-	pub message:   Option<Message>
+	pub update_id:      i64,
+	pub message:        Option<Message>,
+	pub edited_message: Option<Message>,
+	pub inline_query:   Option<InlineQuery>,
+	pub callback_query: Option<CallbackQuery>
+}
+
+impl Update {
+	/// Parse an update pushed to a webhook.
+	pub fn from_reader<R: Read>(reader: R) -> Result<Update, Error> {
+		serde_json::from_reader(reader).map_err(|e| Error::Deserialization(e.to_string()))
+	}
 }
 
 #[derive(Serialize)]
 struct UpdateRequest<'a> {
 	offset:          i64,
 	timeout:         i64,
-	#[serde(skip_serializing_if="Vec::is_empty")]
-	allowed_updates: &'a Vec<&'a str>
+	#[serde(skip_serializing_if="is_empty")]
+	allowed_updates: &'a [&'a str]
+}
+
+#[derive(Serialize)]
+struct WebhookRequest<'a> {
+	url:             &'a str,
+	#[serde(skip_serializing_if="str::is_empty")]
+	secret_token:    &'a str,
+	#[serde(skip_serializing_if="is_empty")]
+	allowed_updates: &'a [&'a str]
+}
+
+fn is_empty(x: &&[&str]) -> bool {
+	x.is_empty()
+}
+
+#[derive(Serialize)]
+pub struct BotCommand {
+	pub command:     String,
+	pub description: String
+}
+
+#[derive(Serialize)]
+struct BotCommandScope<'a> {
+	#[serde(rename="type")]
+	scope_type: &'a str
+}
+
+#[derive(Serialize)]
+struct CommandsRequest<'a> {
+	commands:      &'a [BotCommand],
+	scope:         BotCommandScope<'a>,
+	#[serde(skip_serializing_if="Option::is_none")]
+	language_code: Option<&'a str>
+}
+
+#[derive(Serialize, Clone)]
+pub struct InlineKeyboardButton {
+	pub text:          String,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub callback_data: Option<String>
+}
+impl InlineKeyboardButton {
+	pub fn callback<S: Into<String>, T: Into<String>>(text: S, data: T) -> InlineKeyboardButton {
+		InlineKeyboardButton {
+			text:          text.into(),
+			callback_data: Some(data.into())
+		}
+	}
+}
+
+#[derive(Serialize, Clone)]
+pub struct InlineKeyboardMarkup {
+	pub inline_keyboard: Vec<Vec<InlineKeyboardButton>>
+}
+impl InlineKeyboardMarkup {
+	pub fn new() -> InlineKeyboardMarkup {
+		InlineKeyboardMarkup {
+			inline_keyboard: Vec::new()
+		}
+	}
+}
+
+/// Maximum length of a message text in UTF-16 code units.
+pub const MAX_TEXT_LENGTH: usize = 4096;
+
+fn utf16_len(s: &str) -> usize {
+	s.chars().map(char::len_utf16).sum()
+}
+
+/// Whether `s` ends inside an HTML tag or entity.
+fn ends_in_markup(s: &str) -> bool {
+	let tag = s.rfind('<') > s.rfind('>');
+	let entity = s.rfind('&').map_or(false, |i| !s[i..].contains(|c: char| c == ';' || c.is_whitespace()));
+	tag || entity
+}
+
+/// Opening tags not closed by the end of `s`, outermost first.
+fn open_tags(s: &str) -> Vec<&str> {
+	let mut open = Vec::new();
+	let mut rest = s;
+	loop {
+		let start = match rest.find('<') {
+			None    => break,
+			Some(x) => x
+		};
+		let end = match rest[start..].find('>') {
+			None    => break,
+			Some(x) => start + x + 1
+		};
+		let tag = &rest[start..end];
+		if tag.starts_with("</") {
+			open.pop();
+		} else {
+			open.push(tag);
+		}
+		rest = &rest[end..];
+	}
+	open
+}
+
+/// Byte offsets at which `s` is split so the first part has at most `max` UTF-16
+/// code units, the end of the first part and the start of the second one.
+fn find_cut(s: &str, max: usize, html: bool) -> (usize, usize) {
+	// longest prefix that fits
+	let mut n = 0;
+	let mut end = s.len();
+	for (i, c) in s.char_indices() {
+		n += c.len_utf16();
+		if n > max {
+			end = i;
+			break;
+		}
+	}
+	let head = &s[..end];
+
+	for sep in ["\n\n", "\n", " "].iter() {
+		let cut = head.rmatch_indices(sep)
+				.map(|(i, _)| (i, i + sep.len()))
+				.filter(|&(i, _)| i > 0 && !(html && ends_in_markup(&head[..i])))
+				.next();
+		if cut.is_some() {
+			return cut.unwrap();
+		}
+	}
+
+	let mut i = end;
+	while html && i > 0 {
+		if ends_in_markup(&head[..i]) {
+			i = head[..i].rfind(|c: char| c == '<' || c == '&').unwrap();
+		} else if head[..i].ends_with('>') && !head[..i].ends_with("/>") {
+			// do not leave an opening tag empty
+			let k = match head[..i].rfind('<') {
+				None    => break, // a literal '>'
+				Some(x) => x
+			};
+			if head[k..].starts_with("</") {
+				break;
+			}
+			i = k;
+		} else {
+			break;
+		}
+	}
+	if i == 0 {
+		// a single tag longer than the limit
+		i = end;
+	}
+	(i, i)
+}
+
+/// Split `text` into parts of at most `MAX_TEXT_LENGTH` UTF-16 code units,
+/// preferably at blank lines, otherwise at line breaks or spaces. If `html` is
+/// set, HTML tags and entities are never cut and tags open at a cut are closed
+/// and reopened in the next part.
+pub fn split_text(text: &str, html: bool) -> Vec<String> {
+	let mut parts = Vec::new();
+	let mut rest  = String::from(text);
+	while utf16_len(&rest) > MAX_TEXT_LENGTH {
+		let (i, j) = find_cut(&rest, MAX_TEXT_LENGTH, html);
+		let mut part = String::from(&rest[..i]);
+		let mut next = String::new();
+		if html {
+			// tags do not count towards the limit
+			let open = open_tags(&rest[..i]);
+			for tag in open.iter().rev() {
+				let name = tag[1..(tag.len() - 1)].split_whitespace().next().unwrap_or("");
+				part.push_str(&format!("</{}>", name));
+			}
+			for tag in open.iter() {
+				next.push_str(tag);
+			}
+		}
+		next.push_str(&rest[j..]);
+		parts.push(part);
+		rest = next;
+	}
+	parts.push(rest);
+	parts
+}
+
+/// Shorten the plain text `text` to a single message, `hint` is appended if
+/// anything is cut off.
+pub fn truncate_text(text: &str, hint: &str) -> String {
+	if utf16_len(text) <= MAX_TEXT_LENGTH {
+		return String::from(text);
+	}
+	let (i, _) = find_cut(text, MAX_TEXT_LENGTH - utf16_len(hint), false);
+	format!("{}{}", &text[..i], hint)
 }
 
 #[derive(Serialize)]
@@ -140,18 +425,151 @@ pub struct OutgoingText {
 	pub disable_notification: bool,
 	#[serde(skip_serializing_if="Option::is_none")]
 	pub parse_mode:           Option<String>,
+	/// menus link every meal, a preview of the first one is only in the way
+	pub disable_web_page_preview: bool,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub reply_to_message_id:  Option<i64>,
 	#[serde(skip_serializing_if="Option::is_none")]
-	pub reply_to_message_id:  Option<i64>
+	pub reply_markup:         Option<InlineKeyboardMarkup>
 }
 impl OutgoingText {
+	pub fn new<S: Into<String>>(chat_id: i64, text: S) -> OutgoingText {
+		OutgoingText {
+			chat_id:    chat_id,
+			text:       text.into(),
+			parse_mode: None,
+			disable_web_page_preview: true,
+			disable_notification: false,
+			reply_to_message_id:  None,
+			reply_markup:         None
+		}
+	}
+
+	/// Split into several messages respecting `MAX_TEXT_LENGTH`, only the first
+	/// one replies and only the last one carries the keyboard.
+	pub fn split(&self) -> Vec<OutgoingText> {
+		let html = self.parse_mode.as_ref().map_or(false, |x| x.eq_ignore_ascii_case("html"));
+		let parts = split_text(&self.text, html);
+		let n = parts.len();
+		parts.into_iter().enumerate().map(|(i, text)| OutgoingText {
+			chat_id:              self.chat_id,
+			text:                 text,
+			disable_notification: self.disable_notification,
+			parse_mode:           self.parse_mode.clone(),
+			disable_web_page_preview: self.disable_web_page_preview,
+			reply_to_message_id:  if i == 0 { self.reply_to_message_id } else { None },
+			reply_markup:         if i == n - 1 { self.reply_markup.clone() } else { None }
+		}).collect()
+	}
+
+	/// Edit of the already sent message `message_id` to this text.
+	pub fn to_edit(&self, message_id: i64) -> EditText {
+		EditText {
+			chat_id:      self.chat_id,
+			message_id:   message_id,
+			text:         self.text.clone(),
+			parse_mode:   self.parse_mode.clone(),
+			disable_web_page_preview: self.disable_web_page_preview,
+			reply_markup: self.reply_markup.clone()
+		}
+	}
+
 	fn is_true(b: &bool) -> bool {
 		*b
 	}
 }
 
+#[derive(Serialize)]
+pub struct EditText {
+	pub chat_id:      i64,
+	pub message_id:   i64,
+	pub text:         String,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub parse_mode:   Option<String>,
+	pub disable_web_page_preview: bool,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub reply_markup: Option<InlineKeyboardMarkup>
+}
+
+#[derive(Serialize)]
+struct DeleteRequest {
+	chat_id:    i64,
+	message_id: i64
+}
+
+#[derive(Serialize)]
+pub struct CallbackAnswer {
+	pub callback_query_id: String,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub text:              Option<String>
+}
+impl CallbackAnswer {
+	pub fn new(callback_query_id: String) -> CallbackAnswer {
+		CallbackAnswer {
+			callback_query_id: callback_query_id,
+			text:              None
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct InputTextMessageContent {
+	pub message_text: String,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub parse_mode:   Option<String>,
+	pub disable_web_page_preview: bool
+}
+
+#[derive(Serialize)]
+pub struct InlineQueryResultArticle {
+	#[serde(rename="type")]
+	result_type:               &'static str,
+	pub id:                    String,
+	pub title:                 String,
+	pub input_message_content: InputTextMessageContent,
+	#[serde(skip_serializing_if="Option::is_none")]
+	pub description:           Option<String>
+}
+impl InlineQueryResultArticle {
+	pub fn new<S: Into<String>, T: Into<String>, U: Into<String>>(id: S, title: T, text: U) -> InlineQueryResultArticle {
+		InlineQueryResultArticle {
+			result_type: "article",
+			id:          id.into(),
+			title:       title.into(),
+			input_message_content: InputTextMessageContent {
+				message_text: text.into(),
+				parse_mode:   None,
+				disable_web_page_preview: true
+			},
+			description: None
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct InlineQueryAnswer {
+	pub inline_query_id: String,
+	pub results:         Vec<InlineQueryResultArticle>,
+	pub cache_time:      i64,
+	pub is_personal:     bool
+}
+impl InlineQueryAnswer {
+	pub fn new(inline_query_id: String) -> InlineQueryAnswer {
+		InlineQueryAnswer {
+			inline_query_id: inline_query_id,
+			results:         Vec::new(),
+			cache_time:      300,
+			is_personal:     true
+		}
+	}
+}
+
+/// Clones share their rate limiter.
+#[derive(Clone)]
 pub struct Api {
 	baseurl: String,
 	client:  reqwest::Client,
+	limiter: Arc<RateLimiter>,
 	offset:  i64
 }
 impl Api {
@@ -159,6 +577,7 @@ impl Api {
 		Api {
 			baseurl:   format!("https://api.telegram.org/bot{}/", token),
 			client:    reqwest::Client::new(),
+			limiter:   Arc::new(RateLimiter::new()),
 			offset:    0
 		}
 	}
@@ -166,19 +585,21 @@ impl Api {
 	fn get_result<T>(resp: Response) -> Result<T, Error> where for<'de> T: serde::Deserialize<'de> {
 		if resp.ok {
 			match resp.result {
-				None    => Err(Error::new(format!("unexpected JSON response"))),
+				None    => Err(Error::Deserialization(String::from("unexpected JSON response"))),
 				Some(r) => match serde_json::from_value::<T>(r) {
-					Err(_) => Err(Error::new("unexpected JSON result")),
+					Err(e) => Err(Error::Deserialization(format!("unexpected JSON result: {}", e))),
 					Ok(x)  => Ok(x)
 				}
 			}
 		} else {
-			Err(Error::new(if resp.error_code.is_some() && resp.description.is_some() {
-				format!("Telegram Bot API Error: {} {}",
-						resp.error_code.unwrap(), resp.description.unwrap())
-			} else {
-				format!("unexpected JSON response")
-			}))
+			match (resp.error_code, resp.description) {
+				(Some(code), Some(description)) => Err(Error::Api {
+					code:        code,
+					description: description,
+					parameters:  resp.parameters
+				}),
+				_ => Err(Error::Deserialization(String::from("unexpected JSON response")))
+			}
 		}
 	}
 
@@ -189,14 +610,18 @@ impl Api {
 				.json(data)
 				.send();
 		match resp {
-			Err(e)     => Err(Error::new(format!("reqwest error: {}", e))),
+			Err(e)     => Err(Error::Network(e)),
 			Ok(mut re) => if re.status().is_success() {
 				match re.json() {
-					Err(e) => Err(Error::new(format!("deserialization error: {}", e))),
+					Err(e) => Err(Error::Deserialization(e.to_string())),
 					Ok(r)  => Api::get_result(r)
 				}
 			} else {
-				Err(Error::new(format!("Telegram Bot API HTTP error: {}", re.status())))
+				// errors like flood control come with details
+				match re.json::<Response>() {
+					Err(_) => Err(Error::Http(re.status())),
+					Ok(r)  => Api::get_result(r)
+				}
 			}
 		}
 	}
@@ -205,7 +630,7 @@ impl Api {
 		self.api_call("getMe", &())
 	}
 
-	pub fn get_updates(&mut self, allowed_updates: &Vec<&str>) -> Result<Vec<Update>, Error> {
+	pub fn get_updates(&mut self, allowed_updates: &[&str]) -> Result<Vec<Update>, Error> {
 		let req = UpdateRequest {
 			offset:          self.offset,
 			timeout:         30,
@@ -214,11 +639,130 @@ impl Api {
 		self.api_call("getUpdates", &req)
 	}
 
+	pub fn set_webhook(&self, url: &str, secret_token: &str, allowed_updates: &[&str]) -> Result<bool, Error> {
+		let req = WebhookRequest {
+			url:             url,
+			secret_token:    secret_token,
+			allowed_updates: allowed_updates
+		};
+		self.api_call("setWebhook", &req)
+	}
+
+	pub fn delete_webhook(&self) -> Result<bool, Error> {
+		self.api_call("deleteWebhook", &())
+	}
+
+	/// Set the command list shown in chats of type `scope` (e.g.
+	/// `all_private_chats`) to users with the language `language_code`, or to
+	/// everyone if `None`.
+	pub fn set_my_commands(&self, commands: &[BotCommand], scope: &str, language_code: Option<&str>) -> Result<bool, Error> {
+		let req = CommandsRequest {
+			commands:      commands,
+			scope:         BotCommandScope { scope_type: scope },
+			language_code: language_code
+		};
+		self.api_call("setMyCommands", &req)
+	}
+
+	pub fn answer_inline_query(&self, answer: &InlineQueryAnswer) -> Result<bool, Error> {
+		self.api_call("answerInlineQuery", answer)
+	}
+
+	pub fn answer_callback_query(&self, answer: &CallbackAnswer) -> Result<bool, Error> {
+		self.api_call("answerCallbackQuery", answer)
+	}
+
+	pub fn edit_text(&self, edit: &EditText) -> Result<Message, Error> {
+		self.limiter.wait(edit.chat_id);
+		self.api_call("editMessageText", edit)
+	}
+
+	pub fn delete_message(&self, chat_id: i64, message_id: i64) -> Result<bool, Error> {
+		let req = DeleteRequest {
+			chat_id:    chat_id,
+			message_id: message_id
+		};
+		self.api_call("deleteMessage", &req)
+	}
+
 	pub fn send_text(&self, msg: &OutgoingText) -> Result<Message, Error> {
+		self.limiter.wait(msg.chat_id);
 		return self.api_call("sendMessage", msg);
 	}
 
 	pub fn set_latest_update(&mut self, latest_update: i64) {
 		self.offset = latest_update + 1;
 	}
+
+	pub fn get_offset(&self) -> i64 {
+		self.offset
+	}
+
+	pub fn set_offset(&mut self, offset: i64) {
+		self.offset = offset;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{open_tags, split_text, truncate_text, utf16_len, MAX_TEXT_LENGTH};
+
+	#[test]
+	fn open_tags_are_outermost_first() {
+		assert_eq!(open_tags("<b>a<i>b</i><a href=\"x\">c"), vec!["<b>", "<a href=\"x\">"]);
+		assert!(open_tags("<b>a</b> 1 > 0").is_empty());
+	}
+
+	#[test]
+	fn split_never_cuts_tags_or_entities() {
+		let text = format!("{} <a href=\"https://example.org/\">link</a>", "x".repeat(MAX_TEXT_LENGTH - 16));
+		let parts = split_text(&text, true);
+		assert_eq!(parts, vec!["x".repeat(MAX_TEXT_LENGTH - 16), String::from("<a href=\"https://example.org/\">link</a>")]);
+
+		let text = format!("{}&amp;y", "x".repeat(MAX_TEXT_LENGTH - 2));
+		let parts = split_text(&text, true);
+		assert_eq!(parts, vec!["x".repeat(MAX_TEXT_LENGTH - 2), String::from("&amp;y")]);
+	}
+
+	#[test]
+	fn split_reopens_nested_tags() {
+		let text = format!("<b><i>{}</i></b>", "word ".repeat(1000).trim_right());
+		let parts = split_text(&text, true);
+		assert_eq!(parts.len(), 2);
+		for part in parts.iter() {
+			assert!(part.starts_with("<b><i>word"), "{}", part);
+			assert!(part.ends_with("word</i></b>"), "{}", part);
+			assert!(open_tags(part).is_empty());
+		}
+	}
+
+	#[test]
+	fn split_keeps_surrogate_pairs() {
+		let text = format!("x{}", "\u{1F600}".repeat(MAX_TEXT_LENGTH / 2));
+		let parts = split_text(&text, false);
+		assert_eq!(parts.len(), 2);
+		assert_eq!(utf16_len(&parts[0]), MAX_TEXT_LENGTH - 1);
+		assert_eq!(parts[1], "\u{1F600}");
+		assert_eq!(parts.concat(), text);
+	}
+
+	#[test]
+	fn split_without_separators() {
+		let parts = split_text(&"x".repeat(5000), false);
+		assert_eq!(parts, vec!["x".repeat(MAX_TEXT_LENGTH), "x".repeat(5000 - MAX_TEXT_LENGTH)]);
+
+		// a '>' that does not close a tag
+		let text = format!("{}>{}", "x".repeat(MAX_TEXT_LENGTH - 1), "y".repeat(10));
+		let parts = split_text(&text, true);
+		assert_eq!(parts, vec![format!("{}>", "x".repeat(MAX_TEXT_LENGTH - 1)), "y".repeat(10)]);
+	}
+
+	#[test]
+	fn truncate_appends_hint() {
+		let text = "word ".repeat(1000);
+		let short = truncate_text(&text, "\n…");
+		assert!(utf16_len(&short) <= MAX_TEXT_LENGTH);
+		assert!(short.ends_with("word\n…"), "{}", short);
+		assert_eq!(truncate_text("word", "\n…"), "word");
+	}
 }
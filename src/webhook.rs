@@ -0,0 +1,51 @@
+extern crate tiny_http;
+
+use tg;
+
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+fn respond(req: tiny_http::Request, status: u16) {
+	match req.respond(tiny_http::Response::empty(tiny_http::StatusCode(status))) {
+		Err(e) => warn!("cannot respond to webhook request: {}", e),
+		Ok(_)  => {}
+	}
+}
+
+/// Receive updates pushed by Telegram on `listen` and pass them to `handler`,
+/// requests without the right secret token are rejected.
+pub fn serve<F>(listen: &str, secret: &str, mut handler: F) -> Result<(), String> where F: FnMut(tg::Update) {
+	let server = tiny_http::Server::http(listen).map_err(|e| format!("cannot listen on {}: {}", listen, e))?;
+	info!("listening for webhook requests on {}", listen);
+
+	for mut req in server.incoming_requests() {
+		if *req.method() != tiny_http::Method::Post {
+			respond(req, 405);
+			continue;
+		}
+
+		if !secret.is_empty() {
+			let authorized = req.headers().iter()
+					.any(|h| h.field.equiv(SECRET_HEADER) && h.value.as_str() == secret);
+			if !authorized {
+				warn!("webhook request from {} with wrong secret token", req.remote_addr());
+				respond(req, 403);
+				continue;
+			}
+		}
+
+		let upd = tg::Update::from_reader(req.as_reader());
+		match upd {
+			Err(e) => {
+				warn!("invalid webhook request: {}", e);
+				respond(req, 400);
+			},
+			Ok(upd) => {
+				// answer before handling, Telegram resends updates that take too long
+				respond(req, 200);
+				handler(upd);
+			}
+		}
+	}
+
+	Ok(())
+}